@@ -0,0 +1,346 @@
+use crate::config::AbuseConfig;
+use crate::models::AbuseContact;
+use crate::prefix_tree::{parse_range, PrefixTree};
+use crate::rdap::{RdapClient, RdapError};
+use crate::special_purpose::is_global;
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::net::IpAddr;
+
+// `inetnum` or `inet6num` object and the attributes its abuse contact is found through
+struct NetworkObject {
+    network: String,
+    abuse_mailbox: Option<String>,
+    abuse_c: Option<String>,
+    org: Option<String>,
+}
+
+// `role`, `person` or `organisation` object, keyed by its handle
+struct HandleObject {
+    name: String,
+    abuse_mailbox: Option<String>,
+    abuse_c: Option<String>,
+}
+
+// Who handles abuse reports for an address, taken from a local RPSL database before asking RDAP
+pub struct AbuseContacts {
+    database: PrefixTree<AbuseContact>,
+    rdap: bool,
+}
+
+impl AbuseContacts {
+    pub fn new(config: &AbuseConfig) -> Result<Self, String> {
+        let database = match &config.database_path {
+            Some(path) => read_database(path).map_err(|err| format!("{}: {}", path, err))?,
+            None => PrefixTree::default(),
+        };
+
+        Ok(Self {
+            database,
+            rdap: config.rdap,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.database.len()
+    }
+
+    // Lookups need the database or RDAP, otherwise they never find anything
+    pub fn enabled(&self, rdap: &RdapClient) -> bool {
+        self.len() > 0 || (self.rdap && rdap.enabled)
+    }
+
+    // Blocking when RDAP is asked, run it through `web::block`. RDAP answers come from the
+    // client's per-network cache
+    pub fn lookup(&self, ip: IpAddr, rdap: &RdapClient) -> Option<AbuseContact> {
+        if let Some((_, contact)) = self.database.longest_match(ip) {
+            return Some(contact.clone());
+        }
+        if !self.rdap || !rdap.enabled || !is_global(ip) {
+            return None;
+        }
+
+        match rdap.lookup(ip) {
+            Ok(record) if !record.abuse_contact.email.is_empty() => Some(AbuseContact {
+                email: record.abuse_contact.email,
+                name: record.abuse_contact.name,
+                network: record.network,
+                source: record.source,
+            }),
+            Ok(_) | Err(RdapError::NotFound) => None,
+            Err(RdapError::Upstream(err)) => {
+                log::warn!("Abuse contact lookup for {} failed: {}", ip, err);
+                None
+            }
+        }
+    }
+}
+
+// Only networks with a resolvable abuse mailbox are kept, networks without one fall through to
+// the network containing them. Networks whose range doesn't parse are logged and skipped
+fn read_database(path: &str) -> Result<PrefixTree<AbuseContact>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let reader: Box<dyn Read> = if path.ends_with(".gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut networks = Vec::new();
+    let mut handles = HashMap::new();
+    read_objects(BufReader::new(reader), |object| {
+        let attribute = |key: &str| {
+            object
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+        };
+        let Some((class, key)) = object.first() else {
+            return;
+        };
+
+        match class.as_str() {
+            "inetnum" | "inet6num" => networks.push(NetworkObject {
+                network: key.clone(),
+                abuse_mailbox: attribute("abuse-mailbox"),
+                abuse_c: attribute("abuse-c"),
+                org: attribute("org"),
+            }),
+            "role" | "person" => {
+                if let Some(handle) = attribute("nic-hdl") {
+                    let object = HandleObject {
+                        name: key.clone(),
+                        abuse_mailbox: attribute("abuse-mailbox"),
+                        abuse_c: None,
+                    };
+                    handles.insert(handle.to_uppercase(), object);
+                }
+            }
+            "organisation" => {
+                let object = HandleObject {
+                    name: attribute("org-name").unwrap_or_default(),
+                    abuse_mailbox: attribute("abuse-mailbox"),
+                    abuse_c: attribute("abuse-c"),
+                };
+                handles.insert(key.to_uppercase(), object);
+            }
+            _ => {}
+        }
+    })
+    .map_err(|err| err.to_string())?;
+
+    let mut database = PrefixTree::default();
+    for network in networks {
+        let Some(contact) = resolve(&network, &handles) else {
+            continue;
+        };
+        let Some(prefixes) = parse_range(&network.network) else {
+            log::warn!("{}: skipping `{}`, not a network", path, network.network);
+            continue;
+        };
+        for prefix in prefixes {
+            let contact = AbuseContact {
+                email: contact.0.clone(),
+                name: contact.1.clone(),
+                network: network.network.clone(),
+                source: path.to_string(),
+            };
+            database.insert(prefix, contact);
+        }
+    }
+    Ok(database)
+}
+
+// The network's own `abuse-mailbox`, the role in `abuse-c`, or the abuse contact of its `org`
+fn resolve(
+    network: &NetworkObject,
+    handles: &HashMap<String, HandleObject>,
+) -> Option<(String, String)> {
+    if let Some(mailbox) = &network.abuse_mailbox {
+        return Some((mailbox.clone(), String::new()));
+    }
+
+    let handle = |handle: &Option<String>| {
+        handle
+            .as_ref()
+            .and_then(|handle| handles.get(&handle.to_uppercase()))
+            .filter(|object| object.abuse_mailbox.is_some())
+    };
+    let object = handle(&network.abuse_c).or_else(|| {
+        let org = network
+            .org
+            .as_ref()
+            .and_then(|org| handles.get(&org.to_uppercase()))?;
+        handle(&org.abuse_c).or(org.abuse_mailbox.as_ref().map(|_| org))
+    })?;
+
+    Some((object.abuse_mailbox.clone()?, object.name.clone()))
+}
+
+// Objects are separated by blank lines, `%` and `#` lines are comments. Continuation lines are
+// skipped, none of the attributes used here span several lines
+fn read_objects(
+    mut reader: impl BufRead,
+    mut handle: impl FnMut(&[(String, String)]),
+) -> std::io::Result<()> {
+    let mut object: Vec<(String, String)> = Vec::new();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        // Dumps aren't guaranteed to be UTF-8
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+
+        if text.is_empty() {
+            if !object.is_empty() {
+                handle(&object);
+                object.clear();
+            }
+            if read == 0 {
+                return Ok(());
+            }
+            continue;
+        }
+        if text.starts_with(['%', '#', ' ', '\t', '+']) {
+            continue;
+        }
+        if let Some((key, value)) = text.split_once(':') {
+            // Trailing `# comments` are part of RPSL values
+            let value = value.split('#').next().unwrap_or_default().trim();
+            object.push((key.trim().to_lowercase(), value.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = "\
+% Example RPSL dump
+# with both comment styles
+
+inetnum:        192.0.2.0 - 192.0.2.255
+netname:        EXAMPLE-DIRECT
+descr:          Directly listed mailbox,
+                abuse-mailbox: continuation@example.net
++
+abuse-mailbox:  direct@example.net # trailing comment
+
+inetnum:        192.0.2.64 - 192.0.2.127
+abuse-mailbox:  nested@example.net
+
+inetnum:        198.51.100.0 - 198.51.100.255
+abuse-c:        ar1-test
+
+role:           Example Abuse Team
+nic-hdl:        AR1-TEST
+abuse-mailbox:  abuse@example.net
+
+inet6num:       2001:db8::/32
+org:            ORG-EX1-TEST
+
+organisation:   ORG-EX1-TEST
+org-name:       Example Org
+abuse-c:        AR1-TEST
+
+inetnum:        203.0.113.0 - 203.0.113.127
+org:            ORG-MB1-TEST
+
+organisation:   ORG-MB1-TEST
+org-name:       Mailbox Org
+abuse-mailbox:  noc@example.org
+
+inetnum:        203.0.113.128 - 203.0.113.255
+abuse-c:        MISSING-TEST
+
+inetnum:        not a range
+abuse-mailbox:  broken@example.net
+";
+
+    // Every test reads its own copy, tests run in parallel
+    fn database(name: &str) -> PrefixTree<AbuseContact> {
+        let path = std::env::temp_dir().join(format!("ipeek-{}-{}.db", std::process::id(), name));
+        std::fs::write(&path, DATABASE).unwrap();
+        let database = read_database(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        database.unwrap()
+    }
+
+    fn contact(database: &PrefixTree<AbuseContact>, ip: &str) -> Option<(String, String)> {
+        database
+            .longest_match(ip.parse().unwrap())
+            .map(|(_, contact)| (contact.email.clone(), contact.name.clone()))
+    }
+
+    fn email(email: &str, name: &str) -> Option<(String, String)> {
+        Some((email.to_string(), name.to_string()))
+    }
+
+    #[test]
+    fn read_objects_skips_comments_and_continuation_lines() {
+        let mut objects = Vec::new();
+        read_objects(DATABASE.as_bytes(), |object| objects.push(object.to_vec())).unwrap();
+
+        assert_eq!(objects.len(), 10);
+        assert_eq!(
+            objects[0],
+            [
+                ("inetnum", "192.0.2.0 - 192.0.2.255"),
+                ("netname", "EXAMPLE-DIRECT"),
+                ("descr", "Directly listed mailbox,"),
+                ("abuse-mailbox", "direct@example.net"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn read_database_uses_the_network_mailbox() {
+        let database = database("mailbox");
+        assert_eq!(
+            contact(&database, "192.0.2.1"),
+            email("direct@example.net", "")
+        );
+        assert_eq!(
+            contact(&database, "192.0.2.100"),
+            email("nested@example.net", "")
+        );
+
+        let (_, contact) = database
+            .longest_match("192.0.2.1".parse().unwrap())
+            .unwrap();
+        assert_eq!(contact.network, "192.0.2.0 - 192.0.2.255");
+    }
+
+    #[test]
+    fn read_database_resolves_abuse_c_and_org() {
+        let database = database("handles");
+        // abuse-c -> role, handles are case-insensitive
+        assert_eq!(
+            contact(&database, "198.51.100.1"),
+            email("abuse@example.net", "Example Abuse Team")
+        );
+        // org -> abuse-c -> role
+        assert_eq!(
+            contact(&database, "2001:db8::1"),
+            email("abuse@example.net", "Example Abuse Team")
+        );
+        // org with its own mailbox
+        assert_eq!(
+            contact(&database, "203.0.113.1"),
+            email("noc@example.org", "Mailbox Org")
+        );
+    }
+
+    #[test]
+    fn read_database_skips_unresolvable_and_unparsable_networks() {
+        let database = database("skipped");
+        assert_eq!(contact(&database, "203.0.113.200"), None);
+        assert_eq!(database.len(), 5);
+    }
+}
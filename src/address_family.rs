@@ -0,0 +1,244 @@
+use crate::config::AddressFamilyConfig;
+use crate::util::get_ip;
+use crate::AppState;
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpResponse};
+use rand::RngCore;
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    V4,
+    V6,
+}
+
+impl AddressFamily {
+    // IPv4-mapped IPv6 addresses (`::ffff:192.0.2.1`) from dual-stack sockets count as IPv4
+    pub fn of(ip: IpAddr) -> Self {
+        match ip.to_canonical() {
+            IpAddr::V4(_) => AddressFamily::V4,
+            IpAddr::V6(_) => AddressFamily::V6,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AddressFamily::V4 => "IPv4",
+            AddressFamily::V6 => "IPv6",
+        }
+    }
+}
+
+pub struct AddressFamilies {
+    v4_hostnames: Vec<String>,
+    v6_hostnames: Vec<String>,
+    tokens: DualStackTokens,
+}
+
+#[derive(Clone, Default)]
+pub struct DualStackEntry {
+    pub ipv4: Option<IpAddr>,
+    pub ipv6: Option<IpAddr>,
+    // Seconds until the token expires
+    pub expires_in: u64,
+}
+
+struct DualStackTokens {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, (Instant, DualStackEntry)>>,
+}
+
+impl AddressFamilies {
+    pub fn new(config: &AddressFamilyConfig) -> Self {
+        let normalize = |hostnames: &[String]| {
+            hostnames
+                .iter()
+                .map(|hostname| hostname.to_ascii_lowercase())
+                .collect()
+        };
+
+        Self {
+            v4_hostnames: normalize(&config.v4_hostnames),
+            v6_hostnames: normalize(&config.v6_hostnames),
+            tokens: DualStackTokens {
+                ttl: Duration::from_secs(config.dual_token_ttl_secs),
+                max_entries: config.max_dual_tokens,
+                entries: Mutex::new(HashMap::new()),
+            },
+        }
+    }
+
+    // Address family a host is restricted to, `None` for hosts serving both
+    pub fn required_family(&self, host: &str) -> Option<AddressFamily> {
+        let host = strip_port(host).to_ascii_lowercase();
+
+        if self.v4_hostnames.contains(&host) {
+            Some(AddressFamily::V4)
+        } else if self.v6_hostnames.contains(&host) {
+            Some(AddressFamily::V6)
+        } else {
+            None
+        }
+    }
+
+    // First configured host for a family, used to point clients at the other family
+    pub fn hostname(&self, family: AddressFamily) -> Option<&str> {
+        match family {
+            AddressFamily::V4 => self.v4_hostnames.first(),
+            AddressFamily::V6 => self.v6_hostnames.first(),
+        }
+        .map(|hostname| hostname.as_str())
+    }
+
+    // Starts a new token with the address of the first request, `None` while
+    // `max_dual_tokens` tokens are pending
+    pub fn create_token(&self, ip: IpAddr) -> Option<(String, DualStackEntry)> {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        let now = Instant::now();
+        let mut entries = self.tokens.entries.lock().unwrap();
+        // Expired tokens are normally dropped by `spawn_token_prune`
+        if entries.len() >= self.tokens.max_entries {
+            entries.retain(|_, (created, _)| now.duration_since(*created) < self.tokens.ttl);
+            if entries.len() >= self.tokens.max_entries {
+                return None;
+            }
+        }
+
+        let mut entry = DualStackEntry::default();
+        record(&mut entry, ip);
+        entries.insert(token.clone(), (now, entry.clone()));
+
+        entry.expires_in = self.tokens.ttl.as_secs();
+        Some((token, entry))
+    }
+
+    // Adds the address of a follow-up request, `None` for unknown or expired tokens
+    pub fn complete_token(&self, token: &str, ip: IpAddr) -> Option<DualStackEntry> {
+        let now = Instant::now();
+        let mut entries = self.tokens.entries.lock().unwrap();
+
+        let (created, entry) = entries.get_mut(token)?;
+        let age = now.duration_since(*created);
+        if age >= self.tokens.ttl {
+            entries.remove(token);
+            return None;
+        }
+
+        record(entry, ip);
+        let mut entry = entry.clone();
+        entry.expires_in = (self.tokens.ttl - age).as_secs();
+        Some(entry)
+    }
+}
+
+// Drops expired tokens every TTL, so tokens that are never completed don't pile up
+pub fn spawn_token_prune(state: Arc<AppState>) {
+    actix_web::rt::spawn(async move {
+        let tokens = &state.address_families.tokens;
+        let mut ticker = tokio::time::interval(tokens.ttl);
+        loop {
+            ticker.tick().await;
+            let now = Instant::now();
+            let mut entries = tokens.entries.lock().unwrap();
+            entries.retain(|_, (created, _)| now.duration_since(*created) < tokens.ttl);
+        }
+    });
+}
+
+fn record(entry: &mut DualStackEntry, ip: IpAddr) {
+    let ip = ip.to_canonical();
+    match AddressFamily::of(ip) {
+        AddressFamily::V4 => entry.ipv4 = Some(ip),
+        AddressFamily::V6 => entry.ipv6 = Some(ip),
+    }
+}
+
+// `example.com:8080` -> `example.com`, `[::1]:8080` -> `[::1]`
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(index) if !host[index..].contains(']') => &host[..index],
+        _ => host,
+    }
+}
+
+// Middleware rejecting requests to a v4-only or v6-only host that arrived over the other family,
+// which only happens when a proxy or DNS record is misconfigured
+pub struct AddressFamilyMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for AddressFamilyMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AddressFamilyMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AddressFamilyMiddlewareService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AddressFamilyMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AddressFamilyMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let required = req
+                .app_data::<web::Data<Arc<AppState>>>()
+                .and_then(|state| {
+                    state
+                        .address_families
+                        .required_family(req.connection_info().host())
+                });
+
+            let family = AddressFamily::of(get_ip(req.request()));
+            match required {
+                Some(required) if required != family => {
+                    let response = HttpResponse::MisdirectedRequest()
+                        .content_type("text/plain")
+                        .body(format!(
+                            "This host only answers over {} but the request arrived over {}, \
+                             check the DNS records and proxy configuration\n",
+                            required.name(),
+                            family.name()
+                        ));
+                    Ok(req.into_response(response).map_into_right_body())
+                }
+                _ => Ok(service.call(req).await?.map_into_left_body()),
+            }
+        })
+    }
+}
@@ -0,0 +1,507 @@
+use crate::config::{ApiKeyConfig, RateLimitBudget};
+use crate::models::{ApiKeyUsage, EndpointUsage};
+use crate::rate_limit::{RateLimitDecision, TokenBucket, TokenBucketBudget};
+use crate::util::matched_pattern;
+use crate::AppState;
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+pub const API_KEY_QUERY_PARAM: &str = "api_key";
+
+#[derive(Debug, Deserialize)]
+struct KeyFile {
+    #[serde(default)]
+    keys: Vec<KeyDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyDefinition {
+    key: String,
+    name: String,
+    // Replaces the per-IP budgets for requests made with this key, unlimited when absent
+    quota: Option<RateLimitBudget>,
+    // Endpoint paths this key may use, every endpoint when empty
+    #[serde(default)]
+    endpoints: Vec<String>,
+    #[serde(default)]
+    admin: bool,
+}
+
+// Identity of an authenticated request, stored in the request extensions
+#[derive(Clone)]
+pub struct ApiKeyIdentity {
+    key: String,
+    pub admin: bool,
+}
+
+struct ApiKeyEntry {
+    name: String,
+    endpoints: Vec<String>,
+    admin: bool,
+    quota: Option<(TokenBucketBudget, Mutex<TokenBucket>)>,
+    usage: Mutex<KeyUsage>,
+}
+
+#[derive(Default)]
+struct KeyUsage {
+    requests: u64,
+    rejected: u64,
+    last_used: u64,
+    endpoints: HashMap<String, u64>,
+}
+
+pub enum ApiKeyCheck {
+    Allowed(ApiKeyIdentity),
+    Anonymous,
+    Missing,
+    Unknown,
+    Forbidden,
+}
+
+pub struct ApiKeyStore {
+    required: bool,
+    keys: HashMap<String, ApiKeyEntry>,
+}
+
+impl ApiKeyStore {
+    pub fn disabled() -> Self {
+        Self {
+            required: false,
+            keys: HashMap::new(),
+        }
+    }
+
+    pub fn load(config: &ApiKeyConfig) -> Result<Self, config::ConfigError> {
+        if !config.enabled {
+            return Ok(Self::disabled());
+        }
+
+        let key_file: KeyFile = config::Config::builder()
+            .add_source(config::File::with_name(&config.key_file))
+            .build()?
+            .try_deserialize()?;
+
+        let keys = key_file
+            .keys
+            .into_iter()
+            .map(|definition| {
+                let quota = definition.quota.as_ref().map(|quota| {
+                    let budget = TokenBucketBudget::new(quota);
+                    (budget, Mutex::new(TokenBucket::new(&budget)))
+                });
+
+                (
+                    definition.key,
+                    ApiKeyEntry {
+                        name: definition.name,
+                        endpoints: definition.endpoints,
+                        admin: definition.admin,
+                        quota,
+                        usage: Mutex::new(KeyUsage::default()),
+                    },
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            required: config.required,
+            keys,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.required || !self.keys.is_empty()
+    }
+
+    pub fn check(&self, key: Option<&str>, endpoint: Option<&str>) -> ApiKeyCheck {
+        let (key, entry) = match key {
+            Some(key) => match self.keys.get(key) {
+                Some(entry) => (key, entry),
+                None => return ApiKeyCheck::Unknown,
+            },
+            None if self.required => return ApiKeyCheck::Missing,
+            None => return ApiKeyCheck::Anonymous,
+        };
+
+        let endpoint = endpoint.unwrap_or("unmatched");
+        let allowed = entry.admin
+            || entry.endpoints.is_empty()
+            || entry.endpoints.iter().any(|e| e == endpoint);
+
+        let mut usage = entry.usage.lock().unwrap();
+        usage.requests += 1;
+        usage.last_used = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        *usage.endpoints.entry(endpoint.to_string()).or_default() += 1;
+
+        if !allowed {
+            usage.rejected += 1;
+            return ApiKeyCheck::Forbidden;
+        }
+
+        ApiKeyCheck::Allowed(ApiKeyIdentity {
+            key: key.to_string(),
+            admin: entry.admin,
+        })
+    }
+
+    // Takes a token from the key's own quota, `None` when the key is unlimited
+    pub fn check_quota(&self, identity: &ApiKeyIdentity) -> Option<RateLimitDecision> {
+        let entry = self.keys.get(&identity.key)?;
+        let (budget, bucket) = entry.quota.as_ref()?;
+        let decision = bucket.lock().unwrap().take(budget, Instant::now());
+
+        if !decision.allowed {
+            entry.usage.lock().unwrap().rejected += 1;
+        }
+
+        Some(decision)
+    }
+
+    pub fn usage(&self) -> Vec<ApiKeyUsage> {
+        let mut usage: Vec<ApiKeyUsage> = self
+            .keys
+            .values()
+            .map(|entry| {
+                let usage = entry.usage.lock().unwrap();
+                let mut endpoints: Vec<EndpointUsage> = usage
+                    .endpoints
+                    .iter()
+                    .map(|(endpoint, requests)| EndpointUsage {
+                        endpoint: endpoint.clone(),
+                        requests: *requests,
+                    })
+                    .collect();
+                endpoints.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+
+                ApiKeyUsage {
+                    name: entry.name.clone(),
+                    admin: entry.admin,
+                    requests: usage.requests,
+                    rejected: usage.rejected,
+                    last_used: usage.last_used,
+                    endpoints,
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| a.name.cmp(&b.name));
+        usage
+    }
+}
+
+// Middleware authenticating API keys from the `X-API-Key` header or `api_key` query parameter
+pub struct ApiKeyMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiKeyMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiKeyMiddlewareService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ApiKeyMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let check = match req.app_data::<web::Data<Arc<AppState>>>() {
+                Some(state) if state.api_keys.is_enabled() => {
+                    let key = request_api_key(&req);
+                    let endpoint = matched_pattern(req.request());
+                    state.api_keys.check(key.as_deref(), endpoint.as_deref())
+                }
+                _ => ApiKeyCheck::Anonymous,
+            };
+
+            let response = match check {
+                ApiKeyCheck::Allowed(identity) => {
+                    req.extensions_mut().insert(identity);
+                    return Ok(service.call(req).await?.map_into_left_body());
+                }
+                ApiKeyCheck::Anonymous => {
+                    return Ok(service.call(req).await?.map_into_left_body());
+                }
+                ApiKeyCheck::Missing => HttpResponse::Unauthorized()
+                    .insert_header(("WWW-Authenticate", API_KEY_HEADER))
+                    .body("API key required\n"),
+                ApiKeyCheck::Unknown => HttpResponse::Unauthorized()
+                    .insert_header(("WWW-Authenticate", API_KEY_HEADER))
+                    .body("Invalid API key\n"),
+                ApiKeyCheck::Forbidden => {
+                    HttpResponse::Forbidden().body("API key is not allowed to use this endpoint\n")
+                }
+            };
+
+            Ok(req.into_response(response).map_into_right_body())
+        })
+    }
+}
+
+// The query string with the `api_key` value masked, for the access log and echoed requests
+pub fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| {
+            // Decoded, so `api%5Fkey` is caught as well
+            let name = web::Query::<Vec<(String, String)>>::from_query(pair)
+                .ok()
+                .and_then(|query| query.into_inner().pop())
+                .map(|(name, _)| name);
+            match name {
+                Some(name) if name == API_KEY_QUERY_PARAM => format!("{}=[redacted]", name),
+                _ => pair.to_string(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+// Request line for the access log, `%r` would write API keys passed in the query to the log
+pub fn redacted_request_line(req: &ServiceRequest) -> String {
+    let query = req.query_string();
+    let query = if query.is_empty() {
+        String::new()
+    } else {
+        format!("?{}", redact_query(query))
+    };
+    format!("{} {}{} {:?}", req.method(), req.path(), query, req.version())
+}
+
+fn request_api_key(req: &ServiceRequest) -> Option<String> {
+    if let Some(key) = req.headers().get(API_KEY_HEADER) {
+        if let Ok(key_str) = key.to_str() {
+            return Some(key_str.trim().to_string());
+        }
+    }
+
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.get(API_KEY_QUERY_PARAM).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const KEYS: &str = r#"
+[[keys]]
+key = "limited-key"
+name = "limited"
+endpoints = ["/ip", "/country"]
+quota = { requests_per_minute = 1, burst = 2 }
+
+[[keys]]
+key = "open-key"
+name = "open"
+
+[[keys]]
+key = "admin-key"
+name = "admin"
+endpoints = ["/ip"]
+admin = true
+"#;
+
+    fn store(name: &str, required: bool) -> ApiKeyStore {
+        let path = std::env::temp_dir().join(format!("ipeek-{}-{}.toml", std::process::id(), name));
+        std::fs::write(&path, KEYS).unwrap();
+        let store = ApiKeyStore::load(&ApiKeyConfig {
+            enabled: true,
+            key_file: path.to_string_lossy().into_owned(),
+            required,
+        });
+        std::fs::remove_file(&path).unwrap();
+        store.unwrap()
+    }
+
+    fn identity(store: &ApiKeyStore, key: &str) -> ApiKeyIdentity {
+        match store.check(Some(key), Some("/ip")) {
+            ApiKeyCheck::Allowed(identity) => identity,
+            _ => panic!("{} was not allowed", key),
+        }
+    }
+
+    #[test]
+    fn load_reads_the_key_file() {
+        let store = store("load", false);
+        assert!(store.is_enabled());
+        let names: Vec<(String, bool)> = store
+            .usage()
+            .into_iter()
+            .map(|usage| (usage.name, usage.admin))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("admin".to_string(), true),
+                ("limited".to_string(), false),
+                ("open".to_string(), false)
+            ]
+        );
+
+        assert!(!ApiKeyStore::load(&ApiKeyConfig::default())
+            .unwrap()
+            .is_enabled());
+        let missing = ApiKeyStore::load(&ApiKeyConfig {
+            enabled: true,
+            key_file: "/nonexistent/ipeek-keys.toml".to_string(),
+            required: false,
+        });
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn check_rejects_missing_and_unknown_keys() {
+        // 401 with `WWW-Authenticate` in the middleware
+        let required = store("required", true);
+        assert!(matches!(
+            required.check(None, Some("/ip")),
+            ApiKeyCheck::Missing
+        ));
+        assert!(matches!(
+            required.check(Some("nope"), Some("/ip")),
+            ApiKeyCheck::Unknown
+        ));
+
+        let optional = store("optional", false);
+        assert!(matches!(
+            optional.check(None, Some("/ip")),
+            ApiKeyCheck::Anonymous
+        ));
+        assert!(matches!(
+            optional.check(Some("nope"), Some("/ip")),
+            ApiKeyCheck::Unknown
+        ));
+    }
+
+    #[test]
+    fn check_enforces_the_endpoint_allowlist() {
+        // 403 in the middleware
+        let store = store("endpoints", true);
+        assert!(matches!(
+            store.check(Some("limited-key"), Some("/ip")),
+            ApiKeyCheck::Allowed(_)
+        ));
+        assert!(matches!(
+            store.check(Some("limited-key"), Some("/country")),
+            ApiKeyCheck::Allowed(_)
+        ));
+        assert!(matches!(
+            store.check(Some("limited-key"), Some("/asn")),
+            ApiKeyCheck::Forbidden
+        ));
+        assert!(matches!(
+            store.check(Some("limited-key"), None),
+            ApiKeyCheck::Forbidden
+        ));
+
+        // No allowlist allows everything, admin keys ignore theirs
+        assert!(matches!(
+            store.check(Some("open-key"), Some("/asn")),
+            ApiKeyCheck::Allowed(_)
+        ));
+        assert!(matches!(
+            store.check(Some("admin-key"), Some("/asn")),
+            ApiKeyCheck::Allowed(_)
+        ));
+        assert!(identity(&store, "admin-key").admin);
+        assert!(!identity(&store, "open-key").admin);
+
+        let usage = store.usage();
+        let limited = usage.iter().find(|usage| usage.name == "limited").unwrap();
+        assert_eq!((limited.requests, limited.rejected), (4, 2));
+        let endpoints: Vec<(&str, u64)> = limited
+            .endpoints
+            .iter()
+            .map(|usage| (usage.endpoint.as_str(), usage.requests))
+            .collect();
+        assert_eq!(
+            endpoints,
+            [("/asn", 1), ("/country", 1), ("/ip", 1), ("unmatched", 1)]
+        );
+    }
+
+    #[test]
+    fn check_quota_uses_the_key_budget() {
+        let store = store("quota", false);
+        let limited = identity(&store, "limited-key");
+        assert!(store.check_quota(&limited).unwrap().allowed);
+        assert!(store.check_quota(&limited).unwrap().allowed);
+        let denied = store.check_quota(&limited).unwrap();
+        assert!(!denied.allowed);
+        assert_eq!((denied.limit, denied.retry_after), (2, 60));
+
+        // Keys without a quota aren't rate limited at all
+        assert!(store.check_quota(&identity(&store, "open-key")).is_none());
+
+        let usage = store.usage();
+        let limited = usage.iter().find(|usage| usage.name == "limited").unwrap();
+        assert_eq!(limited.rejected, 1);
+    }
+
+    #[test]
+    fn request_api_key_prefers_the_header() {
+        let req = TestRequest::get()
+            .uri("/ip?api_key=query-key")
+            .insert_header((API_KEY_HEADER, " header-key "))
+            .to_srv_request();
+        assert_eq!(request_api_key(&req).as_deref(), Some("header-key"));
+
+        let req = TestRequest::get()
+            .uri("/ip?format=json&api_key=query-key")
+            .to_srv_request();
+        assert_eq!(request_api_key(&req).as_deref(), Some("query-key"));
+
+        let req = TestRequest::get().uri("/ip").to_srv_request();
+        assert_eq!(request_api_key(&req), None);
+    }
+
+    #[test]
+    fn redact_query_masks_only_the_api_key() {
+        assert_eq!(redact_query("api_key=secret"), "api_key=[redacted]");
+        assert_eq!(
+            redact_query("format=json&api%5Fkey=secret&ip=192.0.2.1"),
+            "format=json&api_key=[redacted]&ip=192.0.2.1"
+        );
+        assert_eq!(redact_query("api_keys=1&key=2"), "api_keys=1&key=2");
+    }
+}
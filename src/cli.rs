@@ -0,0 +1,36 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+// Command line flags, layered over `IPEEK_*` environment variables and the config file
+#[derive(Debug, Parser)]
+#[command(name = "ipeek", version, about = "IP address information service")]
+pub struct Cli {
+    /// Configuration file [default: config.toml, skipped when missing]
+    #[arg(short, long, env = "IPEEK_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on (host:port or unix:/path), replaces the configured listeners
+    #[arg(short, long = "bind", value_name = "ADDRESS")]
+    pub bind: Vec<String>,
+
+    /// Path of the GeoLite2 City database
+    #[arg(long, value_name = "PATH")]
+    pub geo_db: Option<String>,
+
+    /// Path of the GeoLite2 ASN database
+    #[arg(long, value_name = "PATH")]
+    pub asn_db: Option<String>,
+
+    /// Log level or filter, e.g. `debug` or `ipeek=debug,actix_server=warn`
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Download the configured databases when a newer version is published, then exit
+    UpdateDb,
+}
@@ -1,6 +1,13 @@
+use crate::cli::Cli;
+use crate::metrics;
+use crate::prefix_tree::{parse_network, PrefixTree};
+use config::ConfigError;
 use serde::Deserialize;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::time::Duration;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::Path;
+use std::time::{Duration, Instant};
 use trust_dns_resolver::config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
 use trust_dns_resolver::TokioAsyncResolver;
 
@@ -10,6 +17,8 @@ pub struct DnsConfig {
     pub reverse_dns_timeout_ms: u64,
     #[serde(default = "default_blocklist_timeout")]
     pub blocklist_timeout_ms: u64,
+    #[serde(default = "default_canary_query")]
+    pub canary_query: String,
 }
 
 fn default_reverse_dns_timeout() -> u64 {
@@ -20,12 +29,577 @@ fn default_blocklist_timeout() -> u64 {
     2000 // 2 seconds default timeout
 }
 
+fn default_canary_query() -> String {
+    "example.com".to_string()
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            reverse_dns_timeout_ms: default_reverse_dns_timeout(),
+            blocklist_timeout_ms: default_blocklist_timeout(),
+            canary_query: default_canary_query(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct HealthConfig {
+    // Databases older than this are reported as degraded by `/readyz`
+    pub max_database_age_days: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsConfig {
+    #[serde(default = "default_metrics_enabled")]
+    pub enabled: bool,
+    // Serve `/metrics` on a separate listener instead of the main server
+    pub server_address: Option<String>,
+}
+
+fn default_metrics_enabled() -> bool {
+    true
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_metrics_enabled(),
+            server_address: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateLimitBudget {
+    // Sustained rate, 0 disables the budget
+    pub requests_per_minute: u32,
+    // Requests allowed in a burst before the sustained rate applies
+    pub burst: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_cheap_budget")]
+    pub cheap: RateLimitBudget,
+    #[serde(default = "default_expensive_budget")]
+    pub expensive: RateLimitBudget,
+}
+
+fn default_cheap_budget() -> RateLimitBudget {
+    RateLimitBudget {
+        requests_per_minute: 120,
+        burst: 30,
+    }
+}
+
+fn default_expensive_budget() -> RateLimitBudget {
+    RateLimitBudget {
+        requests_per_minute: 20,
+        burst: 5,
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cheap: default_cheap_budget(),
+            expensive: default_expensive_budget(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Key definitions (TOML, YAML or JSON) with per-key quotas and endpoints
+    #[serde(default = "default_api_key_file")]
+    pub key_file: String,
+    // Reject requests without a key instead of treating them as anonymous
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_api_key_file() -> String {
+    "keys.toml".to_string()
+}
+
+impl Default for ApiKeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_file: default_api_key_file(),
+            required: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TracerouteConfig {
+    #[serde(default = "default_traceroute_enabled")]
+    pub enabled: bool,
+    // Allow `?target=` instead of tracing back to the caller
+    #[serde(default)]
+    pub allow_custom_target: bool,
+    #[serde(default = "default_traceroute_max_hops")]
+    pub max_hops: u8,
+    #[serde(default = "default_traceroute_timeout")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_traceroute_receive_timeout")]
+    pub receive_timeout_ms: u64,
+}
+
+fn default_traceroute_enabled() -> bool {
+    false
+}
+
+fn default_traceroute_max_hops() -> u8 {
+    30
+}
+
+fn default_traceroute_timeout() -> u64 {
+    30000 // 30 seconds for the whole trace
+}
+
+fn default_traceroute_receive_timeout() -> u64 {
+    1000 // 1 second per hop
+}
+
+impl Default for TracerouteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_traceroute_enabled(),
+            allow_custom_target: false,
+            max_hops: default_traceroute_max_hops(),
+            timeout_ms: default_traceroute_timeout(),
+            receive_timeout_ms: default_traceroute_receive_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PortCheckConfig {
+    #[serde(default = "default_port_check_enabled")]
+    pub enabled: bool,
+    // Only these ports may be checked, so ipeek can't be used as a port scanner
+    #[serde(default = "default_port_check_allowed_ports")]
+    pub allowed_ports: Vec<u16>,
+    #[serde(default = "default_port_check_timeout")]
+    pub timeout_ms: u64,
+}
+
+fn default_port_check_enabled() -> bool {
+    false
+}
+
+fn default_port_check_allowed_ports() -> Vec<u16> {
+    vec![22, 25, 80, 443, 3389, 8080, 8443, 25565, 32400, 51820]
+}
+
+fn default_port_check_timeout() -> u64 {
+    2000 // 2 seconds
+}
+
+impl Default for PortCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_port_check_enabled(),
+            allowed_ports: default_port_check_allowed_ports(),
+            timeout_ms: default_port_check_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeadersConfig {
+    // Header names (case-insensitive) whose values `/headers` and `/request` hide
+    #[serde(default = "default_redacted_headers")]
+    pub redact: Vec<String>,
+}
+
+fn default_redacted_headers() -> Vec<String> {
+    ["cookie", "authorization", "proxy-authorization", "x-api-key"]
+        .iter()
+        .map(|header| header.to_string())
+        .collect()
+}
+
+impl Default for HeadersConfig {
+    fn default() -> Self {
+        Self {
+            redact: default_redacted_headers(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TlsConfig {
+    // Certificate shared by every listener with `tls = true`
+    #[serde(default = "default_tls_cert_path")]
+    pub cert_path: String,
+    #[serde(default = "default_tls_key_path")]
+    pub key_path: String,
+    // How often the certificate files are checked for changes
+    #[serde(default = "default_tls_reload_interval")]
+    pub reload_interval_secs: u64,
+}
+
+fn default_tls_cert_path() -> String {
+    "cert.pem".to_string()
+}
+
+fn default_tls_key_path() -> String {
+    "key.pem".to_string()
+}
+
+fn default_tls_reload_interval() -> u64 {
+    30
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_path: default_tls_cert_path(),
+            key_path: default_tls_key_path(),
+            reload_interval_secs: default_tls_reload_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddressFamilyConfig {
+    // Hosts that must only be reached over IPv4 / IPv6, e.g. `4.ipeek.io` and `6.ipeek.io`
+    #[serde(default)]
+    pub v4_hostnames: Vec<String>,
+    #[serde(default)]
+    pub v6_hostnames: Vec<String>,
+    // How long a `/dual` token waits for the request over the other address family
+    #[serde(default = "default_dual_token_ttl")]
+    pub dual_token_ttl_secs: u64,
+    // Pending tokens kept at once, `/dual` answers 503 while the limit is reached
+    #[serde(default = "default_max_dual_tokens")]
+    pub max_dual_tokens: usize,
+}
+
+fn default_dual_token_ttl() -> u64 {
+    60
+}
+
+fn default_max_dual_tokens() -> usize {
+    10000
+}
+
+impl Default for AddressFamilyConfig {
+    fn default() -> Self {
+        Self {
+            v4_hostnames: Vec::new(),
+            v6_hostnames: Vec::new(),
+            dual_token_ttl_secs: default_dual_token_ttl(),
+            max_dual_tokens: default_max_dual_tokens(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RdapConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // IANA bootstrap registries, https://data.iana.org/rdap/ipv4.json and ipv6.json
+    #[serde(default = "default_rdap_bootstrap_files")]
+    pub bootstrap_files: Vec<String>,
+    // Sends every query to this server instead of the registry from the bootstrap files, e.g.
+    // an internal mirror or a test server
+    pub base_url: Option<String>,
+    #[serde(default = "default_rdap_timeout")]
+    pub timeout_ms: u64,
+    // Answers are cached for the network they describe, shared by `/rdap` and `/abuse`
+    #[serde(default = "default_rdap_cache_ttl")]
+    pub cache_ttl_secs: u64,
+    #[serde(default = "default_rdap_cache_max_entries")]
+    pub cache_max_entries: usize,
+}
+
+fn default_rdap_bootstrap_files() -> Vec<String> {
+    vec![
+        "/var/lib/ipeek/rdap/ipv4.json".to_string(),
+        "/var/lib/ipeek/rdap/ipv6.json".to_string(),
+    ]
+}
+
+fn default_rdap_timeout() -> u64 {
+    5000 // 5 seconds
+}
+
+fn default_rdap_cache_ttl() -> u64 {
+    86400 // 1 day
+}
+
+fn default_rdap_cache_max_entries() -> usize {
+    10000
+}
+
+impl Default for RdapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bootstrap_files: default_rdap_bootstrap_files(),
+            base_url: None,
+            timeout_ms: default_rdap_timeout(),
+            cache_ttl_secs: default_rdap_cache_ttl(),
+            cache_max_entries: default_rdap_cache_max_entries(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AbuseConfig {
+    // RPSL database dump, e.g. the RIPE `ripe.db.inetnum` and `ripe.db.role` split files
+    // concatenated into one file, optionally gzipped. Checked before RDAP
+    pub database_path: Option<String>,
+    // Asks RDAP when the database has no contact, requires `rdap.enabled`
+    #[serde(default = "default_abuse_rdap")]
+    pub rdap: bool,
+}
+
+fn default_abuse_rdap() -> bool {
+    true
+}
+
+impl Default for AbuseConfig {
+    fn default() -> Self {
+        Self {
+            database_path: None,
+            rdap: default_abuse_rdap(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FeedConfig {
+    // Reported as the list the address was found in, e.g. `spamhaus_drop`
+    pub name: String,
+    // One network or address per line (FireHOL netsets, Spamhaus DROP text or JSON, plain lists)
+    pub path: String,
+    // Free-form kind of listing, e.g. `hijacked` or `internal`
+    #[serde(default)]
+    pub category: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlocklistConfig {
+    // Local CIDR feeds checked next to the DNSBLs
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+    // How often the feed files are checked for changes
+    #[serde(default = "default_feed_reload_interval")]
+    pub feed_reload_interval_secs: u64,
+    // Multiplier per DNSBL, domain list or feed name, lists left out count once
+    #[serde(default)]
+    pub weights: HashMap<String, f64>,
+    // Severity (0-100) per reason, e.g. `DynamicResidential`, or feed category, overriding the
+    // built-in reason severities
+    #[serde(default)]
+    pub severities: HashMap<String, f64>,
+    // Severity of feed categories missing from `severities`
+    #[serde(default = "default_feed_severity")]
+    pub feed_severity: f64,
+    // Scores (0-100) from which the verdict is `suspicious` or `bad` instead of `clean`
+    #[serde(default = "default_suspicious_score")]
+    pub suspicious_score: u32,
+    #[serde(default = "default_bad_score")]
+    pub bad_score: u32,
+}
+
+fn default_feed_reload_interval() -> u64 {
+    60
+}
+
+fn default_feed_severity() -> f64 {
+    50.0
+}
+
+fn default_suspicious_score() -> u32 {
+    20
+}
+
+fn default_bad_score() -> u32 {
+    50
+}
+
+impl Default for BlocklistConfig {
+    fn default() -> Self {
+        Self {
+            feeds: Vec::new(),
+            feed_reload_interval_secs: default_feed_reload_interval(),
+            weights: HashMap::new(),
+            severities: HashMap::new(),
+            feed_severity: default_feed_severity(),
+            suspicious_score: default_suspicious_score(),
+            bad_score: default_bad_score(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct NetworkConfig {
+    // CSV or YAML file mapping internal networks to a site, location and tags
+    pub overlay_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdaterConfig {
+    // Check for new databases in the background while the server runs
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_updater_interval")]
+    pub interval_hours: u64,
+    // Archive URL, `{edition}` is replaced by the edition ID and `{suffix}` by `tar.gz` or
+    // `tar.gz.sha256`, so both the MaxMind API and plain HTTP mirrors work
+    #[serde(default = "default_updater_url")]
+    pub url: String,
+    // MaxMind account, sent as basic auth when both are set
+    pub account_id: Option<String>,
+    pub license_key: Option<String>,
+    #[serde(default = "default_geo_edition")]
+    pub geo_edition: String,
+    #[serde(default = "default_asn_edition")]
+    pub asn_edition: String,
+    #[serde(default = "default_updater_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_updater_interval() -> u64 {
+    24
+}
+
+fn default_updater_url() -> String {
+    "https://download.maxmind.com/geoip/databases/{edition}/download?suffix={suffix}".to_string()
+}
+
+fn default_geo_edition() -> String {
+    "GeoLite2-City".to_string()
+}
+
+fn default_asn_edition() -> String {
+    "GeoLite2-ASN".to_string()
+}
+
+fn default_updater_timeout() -> u64 {
+    300 // 5 minutes per download
+}
+
+impl Default for UpdaterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: default_updater_interval(),
+            url: default_updater_url(),
+            account_id: None,
+            license_key: None,
+            geo_edition: default_geo_edition(),
+            asn_edition: default_asn_edition(),
+            timeout_secs: default_updater_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListenerConfig {
+    // `host:port` or `unix:/path/to/socket`
+    pub address: String,
+    // Serve HTTPS with the certificate from the `tls` section
+    #[serde(default)]
+    pub tls: bool,
+    // IPV6_V6ONLY for IPv6 addresses, the OS default (usually dual-stack) when unset
+    pub v6_only: Option<bool>,
+    #[serde(default = "default_listener_backlog")]
+    pub backlog: u32,
+}
+
+fn default_listener_backlog() -> u32 {
+    1024
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
-    pub server_address: String,
+    // Single plain HTTP listener, superseded by `[[listeners]]`
+    pub server_address: Option<String>,
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
     pub geo_db_path: String,
     pub asn_db_path: String,
+    // Map the databases instead of reading them onto the heap, the pages are shared between
+    // every process serving the same files
+    #[serde(default)]
+    pub mmap_databases: bool,
+    // `env_logger` filter, `RUST_LOG` or `info` when unset
+    pub log_level: Option<String>,
+    // Reverse proxies whose `X-Forwarded-For` / `X-Real-IP` is believed by endpoints that connect
+    // back to the client, everyone else is only ever connected to directly
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
     pub dns: DnsConfig,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub api_keys: ApiKeyConfig,
+    #[serde(default)]
+    pub traceroute: TracerouteConfig,
+    #[serde(default)]
+    pub headers: HeadersConfig,
+    #[serde(default)]
+    pub port_check: PortCheckConfig,
+    #[serde(default)]
+    pub address_family: AddressFamilyConfig,
+    #[serde(default)]
+    pub updater: UpdaterConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub rdap: RdapConfig,
+    #[serde(default)]
+    pub abuse: AbuseConfig,
+    #[serde(default)]
+    pub blocklist: BlocklistConfig,
+}
+
+impl AppConfig {
+    // `[[listeners]]`, or a plain HTTP listener on `server_address` for older configs
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        self.server_address
+            .iter()
+            .map(|address| ListenerConfig {
+                address: address.clone(),
+                tls: false,
+                v6_only: None,
+                backlog: default_listener_backlog(),
+            })
+            .collect()
+    }
+
+    // Validated by `validate`, so every entry parses
+    pub fn trusted_proxies(&self) -> PrefixTree<()> {
+        let mut proxies = PrefixTree::default();
+        for network in self.trusted_proxies.iter().filter_map(|proxy| parse_network(proxy)) {
+            proxies.insert(network, ());
+        }
+        proxies
+    }
 }
 
 const LOCAL_DNS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 53);
@@ -35,6 +609,7 @@ pub struct DnsResolver {
     resolver: TokioAsyncResolver,
     reverse_dns_timeout: Duration,
     blocklist_timeout: Duration,
+    canary_query: String,
 }
 
 impl DnsResolver {
@@ -58,29 +633,313 @@ impl DnsResolver {
             resolver,
             reverse_dns_timeout: Duration::from_millis(config.reverse_dns_timeout_ms),
             blocklist_timeout: Duration::from_millis(config.blocklist_timeout_ms),
+            canary_query: config.canary_query.clone(),
         }
     }
 
     pub async fn reverse_lookup(&self, ip: IpAddr) -> Option<String> {
-        tokio::time::timeout(self.reverse_dns_timeout, self.resolver.reverse_lookup(ip))
-            .await
+        let start = Instant::now();
+        let result =
+            tokio::time::timeout(self.reverse_dns_timeout, self.resolver.reverse_lookup(ip)).await;
+        metrics::observe_dns_lookup("reverse_dns", start.elapsed(), result.is_err());
+
+        result
             .ok()
             .and_then(|result| result.ok())
             .and_then(|ptr_lookup| ptr_lookup.iter().next().map(|name| name.to_string()))
     }
 
-    pub async fn lookup_blocklist(&self, query: String) -> Option<IpAddr> {
-        tokio::time::timeout(self.blocklist_timeout, self.resolver.lookup_ip(query))
-            .await
+    pub async fn lookup_blocklist(&self, provider: &str, query: String) -> Option<IpAddr> {
+        let start = Instant::now();
+        let result =
+            tokio::time::timeout(self.blocklist_timeout, self.resolver.lookup_ip(query)).await;
+        metrics::observe_dns_lookup(provider, start.elapsed(), result.is_err());
+
+        result
             .ok()
             .and_then(|result| result.ok())
             .and_then(|response| response.iter().next())
     }
+
+    pub fn canary_query(&self) -> &str {
+        &self.canary_query
+    }
+
+    // Resolves the configured canary name to check that the resolver answers at all
+    pub async fn probe(&self) -> (Duration, Result<(), String>) {
+        let start = Instant::now();
+        let result = tokio::time::timeout(
+            self.blocklist_timeout,
+            self.resolver.lookup_ip(self.canary_query.as_str()),
+        )
+        .await;
+        let elapsed = start.elapsed();
+        metrics::observe_dns_lookup("canary", elapsed, result.is_err());
+
+        let outcome = match result {
+            Err(_) => Err(format!("timed out after {} ms", self.blocklist_timeout.as_millis())),
+            Ok(Err(err)) => Err(err.to_string()),
+            Ok(Ok(response)) if response.iter().next().is_none() => {
+                Err("empty response".to_string())
+            }
+            Ok(Ok(_)) => Ok(()),
+        };
+
+        (elapsed, outcome)
+    }
+}
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+// Keys set from comma separated `IPEEK_*` variables
+const LIST_KEYS: &[&str] = &[
+    "trusted_proxies",
+    "headers.redact",
+    "port_check.allowed_ports",
+    "address_family.v4_hostnames",
+    "address_family.v6_hostnames",
+    "rdap.bootstrap_files",
+];
+
+// Layers the config file, `IPEEK_*` environment variables (`__` separates sections, e.g.
+// `IPEEK_DNS__REVERSE_DNS_TIMEOUT_MS`) and command line flags, then validates the result
+pub fn load_config(cli: &Cli) -> Result<AppConfig, ConfigError> {
+    // The default file is optional so deployments can be configured through the environment only
+    let file = match &cli.config {
+        Some(path) => config::File::from(path.clone()).required(true),
+        None => config::File::with_name(DEFAULT_CONFIG_PATH).required(false),
+    };
+
+    let environment = LIST_KEYS.iter().fold(
+        config::Environment::with_prefix("IPEEK")
+            .prefix_separator("_")
+            .separator("__")
+            .list_separator(",")
+            .try_parsing(true),
+        |environment, key| environment.with_list_parse_key(key),
+    );
+
+    let mut builder = config::Config::builder()
+        .add_source(file)
+        .add_source(environment)
+        .set_override_option("geo_db_path", cli.geo_db.clone())?
+        .set_override_option("asn_db_path", cli.asn_db.clone())?
+        .set_override_option("log_level", cli.log_level.clone())?;
+
+    if !cli.bind.is_empty() {
+        let listeners: Vec<config::Value> = cli
+            .bind
+            .iter()
+            .map(|address| {
+                config::Value::from(config::Map::from([(
+                    "address".to_string(),
+                    config::Value::from(address.clone()),
+                )]))
+            })
+            .collect();
+        builder = builder.set_override("listeners", listeners)?;
+    }
+
+    let config: AppConfig = builder.build()?.try_deserialize()?;
+    config.validate()?;
+    Ok(config)
+}
+
+impl AppConfig {
+    // Checked separately from `validate` since `ipeek update-db` has to work before the
+    // databases exist
+    pub fn validate_databases(&self) -> Result<(), ConfigError> {
+        require_file("geo_db_path", &self.geo_db_path)?;
+        require_file("asn_db_path", &self.asn_db_path)
+    }
+
+    // Checks what serde can't, every error names the offending key
+    fn validate(&self) -> Result<(), ConfigError> {
+        let listeners = self.listeners();
+        if listeners.is_empty() {
+            return Err(invalid(
+                "listeners",
+                "at least one listener or server_address is required",
+            ));
+        }
+        for (index, listener) in listeners.iter().enumerate() {
+            let key = format!("listeners[{}]", index);
+            let is_unix = listener.address.starts_with("unix:");
+            if !is_unix && !listener.address.contains(':') {
+                return Err(invalid(
+                    &format!("{}.address", key),
+                    format!("`{}` is neither host:port nor unix:/path", listener.address),
+                ));
+            }
+            if listener.tls && is_unix {
+                return Err(invalid(
+                    &format!("{}.tls", key),
+                    "TLS is not supported on Unix sockets",
+                ));
+            }
+            let is_ipv4 = listener.address.parse::<SocketAddrV4>().is_ok();
+            if listener.v6_only.is_some() && is_ipv4 {
+                return Err(invalid(
+                    &format!("{}.v6_only", key),
+                    "only applies to IPv6 addresses",
+                ));
+            }
+        }
+
+        if let Some(proxy) = self
+            .trusted_proxies
+            .iter()
+            .find(|proxy| parse_network(proxy).is_none())
+        {
+            return Err(invalid(
+                "trusted_proxies",
+                format!("`{}` is not an address or network", proxy),
+            ));
+        }
+
+        if listeners.iter().any(|listener| listener.tls) {
+            require_file("tls.cert_path", &self.tls.cert_path)?;
+            require_file("tls.key_path", &self.tls.key_path)?;
+        }
+        if self.api_keys.enabled {
+            require_file("api_keys.key_file", &self.api_keys.key_file)?;
+        }
+        if let Some(overlay_path) = &self.network.overlay_path {
+            require_file("network.overlay_path", overlay_path)?;
+        }
+        if self.rdap.enabled {
+            match &self.rdap.base_url {
+                Some(url) if !url.starts_with("http://") && !url.starts_with("https://") => {
+                    return Err(invalid("rdap.base_url", "must be an http:// or https:// URL"));
+                }
+                Some(_) => {}
+                None => {
+                    for file in &self.rdap.bootstrap_files {
+                        require_file("rdap.bootstrap_files", file)?;
+                    }
+                }
+            }
+        }
+
+        if let Some(database_path) = &self.abuse.database_path {
+            require_file("abuse.database_path", database_path)?;
+        }
+        for (index, feed) in self.blocklist.feeds.iter().enumerate() {
+            let key = format!("blocklist.feeds[{}]", index);
+            if feed.name.is_empty() {
+                return Err(invalid(&format!("{}.name", key), "must not be empty"));
+            }
+            if self.blocklist.feeds[..index]
+                .iter()
+                .any(|other| other.name == feed.name)
+            {
+                return Err(invalid(&format!("{}.name", key), "feed names must be unique"));
+            }
+            require_file(&format!("{}.path", key), &feed.path)?;
+        }
+        for (key, values) in [
+            ("blocklist.weights", &self.blocklist.weights),
+            ("blocklist.severities", &self.blocklist.severities),
+        ] {
+            let invalid_value = values.iter().find(|(_, value)| !is_non_negative(**value));
+            if let Some((name, _)) = invalid_value {
+                return Err(invalid(
+                    &format!("{}.{}", key, name),
+                    "must be a finite number of at least 0",
+                ));
+            }
+        }
+        if !is_non_negative(self.blocklist.feed_severity) {
+            return Err(invalid(
+                "blocklist.feed_severity",
+                "must be a finite number of at least 0",
+            ));
+        }
+        if self.blocklist.bad_score == 0 || self.blocklist.bad_score > 100 {
+            return Err(invalid("blocklist.bad_score", "must be between 1 and 100"));
+        }
+        if self.blocklist.suspicious_score == 0
+            || self.blocklist.suspicious_score > self.blocklist.bad_score
+        {
+            return Err(invalid(
+                "blocklist.suspicious_score",
+                "must be between 1 and blocklist.bad_score",
+            ));
+        }
+
+        for (key, value) in [
+            ("dns.reverse_dns_timeout_ms", self.dns.reverse_dns_timeout_ms),
+            ("dns.blocklist_timeout_ms", self.dns.blocklist_timeout_ms),
+            ("traceroute.timeout_ms", self.traceroute.timeout_ms),
+            ("port_check.timeout_ms", self.port_check.timeout_ms),
+            ("tls.reload_interval_secs", self.tls.reload_interval_secs),
+            ("updater.interval_hours", self.updater.interval_hours),
+            ("updater.timeout_secs", self.updater.timeout_secs),
+            ("rdap.timeout_ms", self.rdap.timeout_ms),
+            (
+                "address_family.dual_token_ttl_secs",
+                self.address_family.dual_token_ttl_secs,
+            ),
+            (
+                "blocklist.feed_reload_interval_secs",
+                self.blocklist.feed_reload_interval_secs,
+            ),
+        ] {
+            if value == 0 {
+                return Err(invalid(key, "must be greater than 0"));
+            }
+        }
+        if self.traceroute.max_hops == 0 {
+            return Err(invalid("traceroute.max_hops", "must be greater than 0"));
+        }
+        // Without a limit the port check connects anywhere as often as it is asked to
+        if self.port_check.enabled && !self.rate_limit.enabled {
+            return Err(invalid(
+                "port_check.enabled",
+                "requires rate_limit.enabled, the port check is only served rate-limited",
+            ));
+        }
+        // Inline `/metrics` is served outside the API key and rate limit checks
+        let api_keys_required = self.api_keys.enabled && self.api_keys.required;
+        if api_keys_required && self.metrics.enabled && self.metrics.server_address.is_none() {
+            return Err(invalid(
+                "metrics.server_address",
+                "required with api_keys.required, or set metrics.enabled = false",
+            ));
+        }
+        if self.port_check.allowed_ports.contains(&0) {
+            return Err(invalid("port_check.allowed_ports", "port 0 can't be checked"));
+        }
+        if !self.updater.url.contains("{edition}") || !self.updater.url.contains("{suffix}") {
+            return Err(invalid(
+                "updater.url",
+                "must contain the `{edition}` and `{suffix}` placeholders",
+            ));
+        }
+        if self.updater.account_id.is_some() != self.updater.license_key.is_some() {
+            return Err(invalid(
+                "updater.license_key",
+                "account_id and license_key have to be set together",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+pub fn invalid(key: &str, message: impl Display) -> ConfigError {
+    ConfigError::Message(format!("invalid value for key `{}`: {}", key, message))
 }
 
-pub fn load_config<P: AsRef<std::path::Path>>(path: P) -> Result<AppConfig, config::ConfigError> {
-    let settings = config::Config::builder()
-        .add_source(config::File::from(path.as_ref().to_path_buf()))
-        .build()?;
-    settings.try_deserialize()
+// Rejects NaN and infinity along with negative numbers
+fn is_non_negative(value: f64) -> bool {
+    value.is_finite() && value >= 0.0
+}
+
+fn require_file(key: &str, path: &str) -> Result<(), ConfigError> {
+    if Path::new(path).is_file() {
+        Ok(())
+    } else {
+        Err(invalid(key, format!("`{}` does not exist or is not a file", path)))
+    }
 }
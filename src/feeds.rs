@@ -0,0 +1,303 @@
+use crate::config::FeedConfig;
+use crate::models::FeedListing;
+use crate::prefix_tree::{parse_network, PrefixTree};
+use serde::Deserialize;
+use std::fs;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+// A line of Spamhaus' JSON DROP lists, the trailing metadata line has no `cidr`
+#[derive(Debug, Deserialize)]
+struct DropEntry {
+    cidr: Option<String>,
+}
+
+struct Feed {
+    config: FeedConfig,
+    networks: RwLock<PrefixTree<()>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+// Local CIDR feeds (FireHOL, Spamhaus DROP, internal deny lists) reported next to the DNSBLs
+pub struct Feeds {
+    feeds: Vec<Feed>,
+}
+
+impl Feeds {
+    pub fn load(configs: &[FeedConfig]) -> Result<Self, String> {
+        let feeds = configs
+            .iter()
+            .map(|config| {
+                let modified = modified(&config.path);
+                let networks =
+                    read_feed(&config.path).map_err(|err| format!("{}: {}", config.path, err))?;
+                Ok(Feed {
+                    config: config.clone(),
+                    networks: RwLock::new(networks),
+                    modified: Mutex::new(modified),
+                })
+            })
+            .collect::<Result<Vec<Feed>, String>>()?;
+
+        Ok(Self { feeds })
+    }
+
+    // Feed names and their number of networks, in configuration order
+    pub fn sizes(&self) -> Vec<(&str, usize)> {
+        self.feeds
+            .iter()
+            .map(|feed| {
+                (
+                    feed.config.name.as_str(),
+                    feed.networks.read().unwrap().len(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Vec<FeedListing> {
+        self.feeds
+            .iter()
+            .filter_map(|feed| {
+                let networks = feed.networks.read().unwrap();
+                let (network, _) = networks.longest_match(ip)?;
+                Some(FeedListing {
+                    feed: feed.config.name.clone(),
+                    category: feed.config.category.clone(),
+                    network: network.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    // Re-reads the feeds whose file changed, a broken file keeps the previous networks in place
+    fn reload_changed(&self) {
+        for feed in &self.feeds {
+            let current = modified(&feed.config.path);
+            let mut last_modified = feed.modified.lock().unwrap();
+            if current.is_none() || current == *last_modified {
+                continue;
+            }
+
+            match read_feed(&feed.config.path) {
+                Ok(networks) => {
+                    log::info!(
+                        "Reloaded {} networks of feed {} from {}",
+                        networks.len(),
+                        feed.config.name,
+                        feed.config.path
+                    );
+                    *feed.networks.write().unwrap() = networks;
+                    *last_modified = current;
+                }
+                Err(err) => log::warn!("Failed to reload feed {}: {}", feed.config.name, err),
+            }
+        }
+    }
+}
+
+// Polls the feed files and reloads them when they change
+pub fn spawn_reload(feeds: Arc<Feeds>, interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately, the feeds were just loaded
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let feeds = feeds.clone();
+            if let Err(err) = tokio::task::spawn_blocking(move || feeds.reload_changed()).await {
+                log::warn!("Feed reload task failed: {}", err);
+            }
+        }
+    });
+}
+
+// One network or address per line, everything after `#` or `;` is a comment (FireHOL and
+// Spamhaus' text format). Lines starting with `{` are Spamhaus' JSON format
+fn read_feed(path: &str) -> Result<PrefixTree<()>, String> {
+    let data = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut networks = PrefixTree::default();
+
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        let network = if line.starts_with('{') {
+            let entry: DropEntry = serde_json::from_str(line)
+                .map_err(|err| format!("line {}: {}", number + 1, err))?;
+            match entry.cidr {
+                Some(cidr) => cidr,
+                None => continue,
+            }
+        } else {
+            let content = line.split(['#', ';']).next().unwrap_or_default();
+            match content.split_whitespace().next() {
+                Some(network) => network.to_string(),
+                None => continue,
+            }
+        };
+
+        let network = parse_network(&network)
+            .ok_or_else(|| format!("line {}: `{}` is not a network", number + 1, network))?;
+        networks.insert(network, ());
+    }
+
+    Ok(networks)
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const FIREHOL: &str = "\
+#
+# firehol_level1
+#
+# Maintainer      : FireHOL
+# Version         : 1720771805
+#
+0.0.0.0/8
+192.0.2.0/24
+198.51.100.7
+2001:db8::/32
+";
+
+    const DROP_TEXT: &str = "\
+; Spamhaus DROP List 2024/07/12 - (c) 2024 The Spamhaus Project SLU
+; Last-Modified: Fri, 12 Jul 2024 08:49:30 GMT
+; Expires: Sat, 13 Jul 2024 09:56:20 GMT
+192.0.2.0/24 ; SBL123456
+203.0.113.0/25 ; SBL654321
+
+";
+
+    const DROP_JSON: &str = r#"{"cidr":"192.0.2.0/24","sblid":"SBL123456","rir":"arin"}
+{"cidr":"2001:db8:1::/48","sblid":"SBL654321","rir":"ripencc"}
+{"type":"metadata","timestamp":1720774150,"size":2,"records":2,"copyright":"(c) 2024 The Spamhaus Project SLU"}
+"#;
+
+    // Every test writes its own file, tests run in parallel
+    fn write(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ipeek-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn read(name: &str, contents: &str) -> Result<PrefixTree<()>, String> {
+        let path = write(name, contents);
+        let networks = read_feed(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        networks
+    }
+
+    fn listed(networks: &PrefixTree<()>, ip: &str) -> Option<String> {
+        networks
+            .longest_match(ip.parse().unwrap())
+            .map(|(network, _)| network.to_string())
+    }
+
+    #[test]
+    fn read_feed_firehol_netset() {
+        let networks = read("firehol_level1.netset", FIREHOL).unwrap();
+        assert_eq!(networks.len(), 4);
+        assert_eq!(
+            listed(&networks, "192.0.2.1").as_deref(),
+            Some("192.0.2.0/24")
+        );
+        assert_eq!(
+            listed(&networks, "198.51.100.7").as_deref(),
+            Some("198.51.100.7/32")
+        );
+        assert_eq!(listed(&networks, "198.51.100.8"), None);
+        assert_eq!(
+            listed(&networks, "2001:db8::1").as_deref(),
+            Some("2001:db8::/32")
+        );
+    }
+
+    #[test]
+    fn read_feed_spamhaus_text() {
+        let networks = read("drop.txt", DROP_TEXT).unwrap();
+        assert_eq!(networks.len(), 2);
+        assert_eq!(
+            listed(&networks, "203.0.113.127").as_deref(),
+            Some("203.0.113.0/25")
+        );
+        assert_eq!(listed(&networks, "203.0.113.128"), None);
+    }
+
+    #[test]
+    fn read_feed_spamhaus_json() {
+        let networks = read("drop_v4.json", DROP_JSON).unwrap();
+        assert_eq!(networks.len(), 2);
+        assert_eq!(
+            listed(&networks, "192.0.2.1").as_deref(),
+            Some("192.0.2.0/24")
+        );
+        assert_eq!(
+            listed(&networks, "2001:db8:1::1").as_deref(),
+            Some("2001:db8:1::/48")
+        );
+    }
+
+    #[test]
+    fn read_feed_rejects_invalid_lines() {
+        let err = read("invalid.netset", "192.0.2.0/24\n192.0.2.0/33\n")
+            .err()
+            .unwrap();
+        assert_eq!(err, "line 2: `192.0.2.0/33` is not a network");
+
+        let err = read("invalid.json", "{\"cidr\": 1}\n").err().unwrap();
+        assert!(err.starts_with("line 1: "), "{}", err);
+    }
+
+    #[test]
+    fn reload_changed_keeps_the_previous_feed_on_errors() {
+        let path = write("reload.netset", "192.0.2.0/24\n");
+        let feeds = Feeds::load(&[FeedConfig {
+            name: "reload".to_string(),
+            path: path.to_string_lossy().into_owned(),
+            category: "test".to_string(),
+        }])
+        .unwrap();
+        let rewrite = |contents: &str, later_secs: u64| {
+            fs::write(&path, contents).unwrap();
+            // Distinct modification times, however coarse the file system's clock
+            let modified = SystemTime::now() + Duration::from_secs(later_secs);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        let networks = |ip: &str| -> Vec<String> {
+            feeds
+                .lookup(ip.parse().unwrap())
+                .into_iter()
+                .map(|listing| listing.network)
+                .collect()
+        };
+
+        rewrite("not a network\n", 10);
+        feeds.reload_changed();
+        assert_eq!(networks("192.0.2.1"), ["192.0.2.0/24"]);
+
+        rewrite("198.51.100.0/24\n", 20);
+        feeds.reload_changed();
+        assert!(networks("192.0.2.1").is_empty());
+        assert_eq!(networks("198.51.100.1"), ["198.51.100.0/24"]);
+        assert_eq!(feeds.sizes(), [("reload", 1)]);
+
+        // A deleted file keeps the feed as well
+        fs::remove_file(&path).unwrap();
+        feeds.reload_changed();
+        assert_eq!(networks("198.51.100.1"), ["198.51.100.0/24"]);
+    }
+}
@@ -1,7 +1,8 @@
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
 use actix_web::http::Uri;
 use actix_web::http::header::{ACCEPT};
-use actix_web::{dev, Error, HttpMessage};
+use actix_web::{dev, web, Error, HttpMessage};
+use std::collections::HashMap;
 use std::fmt;
 use std::future::{ready, Ready};
 use std::path::Path;
@@ -63,6 +64,20 @@ where
             format
         };
 
+        // A `callback` query parameter turns JSON responses into JSONP
+        let format = match format {
+            Format::Json | Format::Plain => {
+                match web::Query::<HashMap<String, String>>::from_query(req.query_string())
+                    .ok()
+                    .and_then(|query| query.get("callback").cloned())
+                {
+                    Some(callback) => Format::Jsonp(callback),
+                    None => format,
+                }
+            }
+            _ => format,
+        };
+
         let clean_path = path
             .strip_suffix(&format!(".{}", ext_str))
             .unwrap_or(path)
@@ -85,6 +100,9 @@ pub enum Format {
     Csv,
     Yml,
     Msgpack,
+    Env,
+    Sse,
+    Jsonp(String),
     Txt,
     Plain,
 }
@@ -99,8 +117,12 @@ impl FromStr for Format {
             "csv" => Ok(Format::Csv),
             "yaml" | "yml" => Ok(Format::Yml),
             "msgpack" => Ok(Format::Msgpack),
+            "env" => Ok(Format::Env),
+            "sse" => Ok(Format::Sse),
             "txt" => Ok(Format::Txt),
-            _ => Ok(Format::Plain),
+            "html" => Ok(Format::Plain),
+            // Not a format, e.g. the last octet of `/convert/192.0.2.1`
+            _ => Err(()),
         }
     }
 }
@@ -115,6 +137,7 @@ impl Format {
                 "text/csv" => return Format::Csv,
                 "application/yaml" | "text/yaml" => return Format::Yml,
                 "application/msgpack" | "application/x-msgpack" => return Format::Msgpack,
+                "text/event-stream" => return Format::Sse,
                 "text/plain" => return Format::Txt,
                 "text/html" => return Format::Plain,
                 _ => continue,
@@ -132,6 +155,9 @@ impl fmt::Display for Format {
             Format::Csv => "csv",
             Format::Yml => "yml",
             Format::Msgpack => "msgpack",
+            Format::Env => "env",
+            Format::Sse => "sse",
+            Format::Jsonp(_) => "json",
             Format::Txt => "txt",
             Format::Plain => "",
         };
@@ -149,7 +175,10 @@ impl Format {
         if let Some(dot_index) = file_name.rfind('.') {
             if dot_index < file_name.len() - 1 {
                 let ext = &file_name[dot_index + 1..];
-                (Format::from_str(ext).unwrap(), ext)
+                match Format::from_str(ext) {
+                    Ok(format) => (format, ext),
+                    Err(()) => (Format::Plain, ""),
+                }
             } else {
                 (Format::Plain, "")
             }
@@ -0,0 +1,49 @@
+use crate::format_middleware::Format;
+use crate::models::{AbuseContact, AbuseRecord};
+use crate::util::{format_response, get_ip};
+use crate::AppState;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+pub async fn abuse_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
+    let ip = get_ip(&req);
+    abuse_response(&req, &state, ip).await
+}
+
+pub async fn abuse_ip_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    ip: web::Path<String>,
+) -> HttpResponse {
+    match ip.parse::<IpAddr>() {
+        Ok(ip) => abuse_response(&req, &state, ip).await,
+        Err(_) => HttpResponse::BadRequest().body("Invalid IP address\n"),
+    }
+}
+
+async fn abuse_response(req: &HttpRequest, state: &Arc<AppState>, ip: IpAddr) -> HttpResponse {
+    if !state.abuse.enabled(&state.rdap) {
+        return HttpResponse::ServiceUnavailable().body("Abuse contact lookups are disabled\n");
+    }
+
+    match get_abuse_contact(ip, state).await {
+        Some(abuse_contact) => {
+            let record = AbuseRecord {
+                ip: ip.to_string(),
+                abuse_contact,
+            };
+            format_response(req.extensions().get::<Format>().unwrap(), &record, false)
+        }
+        None => HttpResponse::NotFound().body("No abuse contact found\n"),
+    }
+}
+
+// Local database first, then RDAP, which blocks and runs on the blocking thread pool
+pub async fn get_abuse_contact(ip: IpAddr, state: &Arc<AppState>) -> Option<AbuseContact> {
+    let state = state.clone();
+    web::block(move || state.abuse.lookup(ip, &state.rdap))
+        .await
+        .ok()
+        .flatten()
+}
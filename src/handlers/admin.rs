@@ -0,0 +1,30 @@
+use crate::api_keys::ApiKeyIdentity;
+use crate::format_middleware::Format;
+use crate::models::ApiKeyUsageResponse;
+use crate::util::format_response;
+use crate::AppState;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+pub async fn usage_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
+    let is_admin = req
+        .extensions()
+        .get::<ApiKeyIdentity>()
+        .is_some_and(|identity| identity.admin);
+
+    if !is_admin {
+        return HttpResponse::Forbidden().body("Admin API key required\n");
+    }
+
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_usage_response(&state),
+        false,
+    )
+}
+
+pub fn get_usage_response(state: &web::Data<Arc<AppState>>) -> ApiKeyUsageResponse {
+    ApiKeyUsageResponse {
+        keys: state.api_keys.usage(),
+    }
+}
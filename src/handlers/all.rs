@@ -1,40 +1,292 @@
 use crate::format_middleware::Format;
-use crate::handlers::asn::get_asn_info;
-use crate::handlers::blocklist::get_blocklist;
+use crate::handlers::abuse::get_abuse_contact;
+use crate::handlers::asn::lookup_asn;
+use crate::handlers::blocklist::{
+    get_blocklist, registered_domain, score, spawn_blocklist_checks, spawn_domain_blocklist_checks,
+};
 use crate::handlers::city::get_city;
 use crate::handlers::country::get_country;
 use crate::handlers::country_code::get_country_code;
 use crate::handlers::region::get_region;
 use crate::handlers::reverse_dns::get_reverse_dns;
-use crate::models::AllResponse;
-use crate::util::{format_response, get_ip};
+use crate::models::{AllResponse, BlocklistEntry, BlocklistRecord, ToPlainText};
+use crate::special_purpose::classify;
+use crate::streaming;
+use crate::user_agent::request_user_agent;
+use crate::util::{format_response, get_ip, sse_event};
 use crate::AppState;
+use actix_web::web::Bytes;
 use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde_json::json;
+use std::net::IpAddr;
 use std::sync::Arc;
+use tokio::sync::mpsc;
+
+// Results of the slow lookups, in the order they finish
+enum SlowField {
+    ReverseDns(String),
+    Blocklist(&'static str, Option<BlocklistEntry>),
+    DomainBlocklist(&'static str, Option<BlocklistEntry>),
+}
 
 pub async fn all_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
-    let info = get_all_response(&req, &state).await;
-    format_response(req.extensions().get::<Format>().unwrap(), &info, false)
+    let format = req.extensions().get::<Format>().unwrap().clone();
+
+    match format {
+        Format::Plain | Format::Txt | Format::Sse => stream_all_response(&req, state, format),
+        _ => {
+            let info = get_all_response(&req, &state).await;
+            format_response(&format, &info, false)
+        }
+    }
 }
 
 pub async fn get_all_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> AllResponse {
-    let ip = get_ip(&req);
-    let reverse_dns = get_reverse_dns(ip, &state.dns_resolver).await.unwrap_or("".to_string());
-    let country = get_country(ip, &state.geo_db).unwrap_or("".to_string());
-    let country_code = get_country_code(ip, &state.geo_db).unwrap_or("".to_string());
-    let region = get_region(ip, &state.geo_db).unwrap_or("".to_string());
-    let city = get_city(ip, &state.geo_db).unwrap_or("".to_string());
-    let asn = get_asn_info(&req, &state.asn_db);
-    let blocklist = get_blocklist(&ip, &state.dns_resolver).await;
+    let ip = get_ip(req);
+    let mut info = get_local_response(req, state);
+    // The blocklist check resolves the PTR name for the domain blocklists anyway
+    info.blocklist = get_blocklist(&ip, state).await;
+    info.reverse_dns = info.blocklist.hostname.clone();
+    info
+}
 
+// Everything answered from the local databases and the request, DNS based fields are left empty
+fn get_local_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> AllResponse {
+    let ip = get_ip(req);
     AllResponse {
         ip: ip.to_string(),
-        reverse_dns,
-        country,
-        country_code,
-        region,
-        city,
-        asn,
-        blocklist,
+        classification: classify(ip),
+        reverse_dns: "".to_string(),
+        country: state
+            .network
+            .country(ip)
+            .or_else(|| get_country(ip, &state.geo_db.get()))
+            .unwrap_or("".to_string()),
+        country_code: state
+            .network
+            .country_code(ip)
+            .or_else(|| get_country_code(ip, &state.geo_db.get()))
+            .unwrap_or("".to_string()),
+        region: state
+            .network
+            .region(ip)
+            .or_else(|| get_region(ip, &state.geo_db.get()))
+            .unwrap_or("".to_string()),
+        city: get_city(ip, &state.geo_db.get()).unwrap_or("".to_string()),
+        asn: state
+            .network
+            .asn(ip)
+            .unwrap_or_else(|| lookup_asn(ip, &state.asn_db.get())),
+        network: state.network.lookup(ip),
+        blocklist: BlocklistRecord {
+            ip: ip.to_string(),
+            blocked: false,
+            score: 0,
+            verdict: "".to_string(),
+            listed_in: Vec::new(),
+            hostname: "".to_string(),
+            domain: "".to_string(),
+            domain_listed_in: Vec::new(),
+            // Answered locally, the DNSBL results are filled in later
+            feed_listed_in: state.feeds.lookup(ip),
+            abuse_contact: None,
+        },
+        user_agent: request_user_agent(req),
     }
 }
+
+// Sends the local fields right away and appends reverse DNS and every DNSBL result as they arrive
+fn stream_all_response(
+    req: &HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    format: Format,
+) -> HttpResponse {
+    let sse = format == Format::Sse;
+    let ip = get_ip(req);
+    let mut info = get_local_response(req, &state);
+    let (tx, body) = streaming::channel();
+
+    let first_chunk = if sse {
+        [
+            sse_event("ip", &json!({ "ip": info.ip }).to_string()),
+            sse_event("classification", &json!(info.classification).to_string()),
+            sse_event(
+                "geo",
+                &json!({
+                    "country": info.country,
+                    "country_code": info.country_code,
+                    "region": info.region,
+                    "city": info.city,
+                })
+                .to_string(),
+            ),
+            sse_event("asn", &json!(info.asn).to_string()),
+            sse_event("network", &json!(info.network).to_string()),
+            sse_event("feeds", &json!(info.blocklist.feed_listed_in).to_string()),
+            sse_event("user_agent", &json!(info.user_agent).to_string()),
+        ]
+        .concat()
+    } else {
+        let mut chunk = format!(
+            "IP: {}\n{}\nCountry: {} ({})\nRegion: {}\nCity: {}\nASN: {}\n{}\nUser agent: {}\n",
+            info.ip,
+            info.classification.to_plain_text(),
+            info.country,
+            info.country_code,
+            info.region,
+            info.city,
+            info.asn.to_plain_text(),
+            info.network.to_plain_text(),
+            info.user_agent.to_plain_text(),
+        );
+        for listing in &info.blocklist.feed_listed_in {
+            chunk.push_str(&format!(
+                "Feed {}: listed ({}, {})\n",
+                listing.feed, listing.category, listing.network
+            ));
+        }
+        chunk
+    };
+    let _ = tx.send(Bytes::from(first_chunk));
+
+    let (field_tx, mut fields) = mpsc::unbounded_channel();
+    spawn_slow_lookups(ip, &state, field_tx);
+    let state = state.get_ref().clone();
+
+    actix_web::rt::spawn(async move {
+        while let Some(field) = fields.recv().await {
+            let chunk = match field {
+                SlowField::ReverseDns(hostname) => {
+                    let chunk = if sse {
+                        sse_event(
+                            "reverse_dns",
+                            &json!({ "reverse_dns": hostname }).to_string(),
+                        )
+                    } else {
+                        format!("Hostname: {}\n", hostname)
+                    };
+                    info.blocklist.domain = registered_domain(&hostname).unwrap_or_default();
+                    info.blocklist.hostname = hostname.clone();
+                    info.reverse_dns = hostname;
+                    chunk
+                }
+                SlowField::Blocklist(dnsbl, entry) => {
+                    let chunk = if sse {
+                        sse_event(
+                            "blocklist",
+                            &json!({
+                                "dnsbl": dnsbl,
+                                "listed": entry.is_some(),
+                                "reason": entry.as_ref().map(|entry| &entry.reason),
+                            })
+                            .to_string(),
+                        )
+                    } else {
+                        match &entry {
+                            Some(entry) => {
+                                format!("Blocklist {}: listed ({:?})\n", dnsbl, entry.reason)
+                            }
+                            None => format!("Blocklist {}: not listed\n", dnsbl),
+                        }
+                    };
+                    info.blocklist.listed_in.extend(entry);
+                    chunk
+                }
+                SlowField::DomainBlocklist(dnsbl, entry) => {
+                    let chunk = if sse {
+                        sse_event(
+                            "domain_blocklist",
+                            &json!({
+                                "dnsbl": dnsbl,
+                                "domain": info.blocklist.domain,
+                                "listed": entry.is_some(),
+                                "reason": entry.as_ref().map(|entry| &entry.reason),
+                            })
+                            .to_string(),
+                        )
+                    } else {
+                        match &entry {
+                            Some(entry) => format!(
+                                "Domain blocklist {} ({}): listed ({:?})\n",
+                                dnsbl, info.blocklist.domain, entry.reason
+                            ),
+                            None => format!(
+                                "Domain blocklist {} ({}): not listed\n",
+                                dnsbl, info.blocklist.domain
+                            ),
+                        }
+                    };
+                    info.blocklist.domain_listed_in.extend(entry);
+                    chunk
+                }
+            };
+
+            if tx.send(Bytes::from(chunk)).is_err() {
+                return;
+            }
+        }
+
+        info.blocklist.blocked = !info.blocklist.listed_in.is_empty()
+            || !info.blocklist.domain_listed_in.is_empty()
+            || !info.blocklist.feed_listed_in.is_empty();
+        if info.blocklist.blocked {
+            info.blocklist.abuse_contact = get_abuse_contact(ip, &state).await;
+        }
+        score(&mut info.blocklist, &state.blocklist);
+        let last_chunk = if sse {
+            sse_event(
+                "done",
+                &serde_json::to_string(&info).unwrap_or_else(|_| "{}".to_string()),
+            )
+        } else {
+            let mut chunk = format!(
+                "Blocked: {}\nScore: {} ({})\n",
+                if info.blocklist.blocked { "yes" } else { "no" },
+                info.blocklist.score,
+                info.blocklist.verdict
+            );
+            if let Some(abuse_contact) = &info.blocklist.abuse_contact {
+                chunk.push_str(&format!("Abuse contact: {}\n", abuse_contact.to_plain_text()));
+            }
+            chunk
+        };
+        let _ = tx.send(Bytes::from(last_chunk));
+    });
+
+    let content_type = if sse {
+        "text/event-stream"
+    } else {
+        "text/plain"
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Cache-Control", "no-cache"))
+        .body(body)
+}
+
+fn spawn_slow_lookups(
+    ip: IpAddr,
+    state: &web::Data<Arc<AppState>>,
+    fields: mpsc::UnboundedSender<SlowField>,
+) {
+    // The domain blocklists need the PTR name, they are queried once it resolved
+    let resolver = state.dns_resolver.clone();
+    let reverse_dns_fields = fields.clone();
+    tokio::spawn(async move {
+        let hostname = get_reverse_dns(ip, &resolver).await.unwrap_or_default();
+        let mut domain_results = spawn_domain_blocklist_checks(&hostname, &resolver);
+        if reverse_dns_fields.send(SlowField::ReverseDns(hostname)).is_err() {
+            return;
+        }
+        while let Some((dnsbl, entry)) = domain_results.recv().await {
+            let _ = reverse_dns_fields.send(SlowField::DomainBlocklist(dnsbl, entry));
+        }
+    });
+
+    let mut blocklist_results = spawn_blocklist_checks(&ip, &state.dns_resolver);
+    tokio::spawn(async move {
+        while let Some((dnsbl, entry)) = blocklist_results.recv().await {
+            let _ = fields.send(SlowField::Blocklist(dnsbl, entry));
+        }
+    });
+}
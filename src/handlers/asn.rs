@@ -1,4 +1,5 @@
 use crate::format_middleware::Format;
+use crate::metrics;
 use crate::models::AsnRecord;
 use crate::util::{format_response, get_ip};
 use crate::AppState;
@@ -15,17 +16,22 @@ pub async fn asn_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> H
     )
 }
 
-pub fn get_asn_info(req: &HttpRequest, asn_db: &Reader<Vec<u8>>) -> AsnRecord {
-    let ip: IpAddr = get_ip(&req);
+pub fn get_asn_info<S: AsRef<[u8]>>(req: &HttpRequest, asn_db: &Reader<S>) -> AsnRecord {
+    lookup_asn(get_ip(req), asn_db)
+}
 
-    asn_db.lookup(ip).unwrap_or(AsnRecord {
+pub fn lookup_asn<S: AsRef<[u8]>>(ip: IpAddr, asn_db: &Reader<S>) -> AsnRecord {
+    metrics::observe_mmdb_lookup("asn", asn_db.lookup(ip)).unwrap_or(AsnRecord {
         aso: Some("".to_string()),
         asn: Some(0),
     })
 }
 
 pub fn get_asn_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> AsnRecord {
-    let asn_info = get_asn_info(&req, &state.asn_db);
+    let asn_info = state
+        .network
+        .asn(get_ip(req))
+        .unwrap_or_else(|| get_asn_info(req, &state.asn_db.get()));
     AsnRecord {
         aso: asn_info.aso,
         asn: asn_info.asn,
@@ -1,12 +1,16 @@
-use crate::config::DnsResolver;
+use crate::config::{BlocklistConfig, DnsResolver};
 use crate::format_middleware::Format;
+use crate::handlers::abuse::get_abuse_contact;
+use crate::handlers::reverse_dns::get_reverse_dns;
 use crate::models::BlocklistReason;
 use crate::models::{BlocklistEntry, BlocklistRecord};
+use crate::special_purpose::is_global;
 use crate::util::{format_response, get_ip};
 use actix_web::{HttpMessage, HttpRequest, HttpResponse};
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
+use tokio::sync::mpsc;
 use crate::AppState;
 use actix_web::web;
 
@@ -16,33 +20,132 @@ const BLOCKLISTS: &[&str] = &[
     "b.barracudacentral.org",
 ];
 
+// Domain blocklists, queried with the registered domain of the IP's PTR name
+const DOMAIN_BLOCKLISTS: &[&str] = &["dbl.spamhaus.org", "multi.surbl.org", "multi.uribl.com"];
+
+// Second-level labels under which country code TLDs register domains, e.g. `example.co.uk`
+const SECOND_LEVEL_LABELS: &[&str] = &[
+    "ac", "co", "com", "edu", "go", "gov", "ne", "net", "or", "org",
+];
+
 pub async fn blocklist_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
-    format_response(
-        req.extensions().get::<Format>().unwrap(),
-        &get_blocklist_response(&req, &state).await,
-        false,
-    )
+    let info = get_blocklist_response(&req, &state).await;
+    format_response(req.extensions().get::<Format>().unwrap(), &info, false)
 }
 
 pub async fn get_blocklist_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> BlocklistRecord {
-    let ip = get_ip(&req);
-    get_blocklist(&ip, &state.dns_resolver).await
+    let ip = get_ip(req);
+    get_blocklist(&ip, state).await
 }
 
-pub async fn get_blocklist(ip: &IpAddr, resolver: &DnsResolver) -> BlocklistRecord {
-    let listed_info = check_blocklists(ip, resolver).await;
+// Listed addresses come with the contact to report them to
+pub async fn get_blocklist(ip: &IpAddr, state: &Arc<AppState>) -> BlocklistRecord {
+    let hostname_check = tokio::spawn(check_hostname(*ip, state.dns_resolver.clone()));
+    let listed_info = check_blocklists(ip, &state.dns_resolver).await;
+    let (hostname, domain_listed_info) = hostname_check.await.unwrap_or_default();
+    let feed_listed_info = state.feeds.lookup(*ip);
+    let blocked =
+        !listed_info.is_empty() || !domain_listed_info.is_empty() || !feed_listed_info.is_empty();
+    let abuse_contact = if blocked {
+        get_abuse_contact(*ip, state).await
+    } else {
+        None
+    };
 
-    BlocklistRecord {
+    let mut record = BlocklistRecord {
         ip: ip.to_string(),
-        blocked: !listed_info.is_empty(),
+        blocked,
+        score: 0,
+        verdict: "".to_string(),
         listed_in: listed_info,
+        domain: registered_domain(&hostname).unwrap_or_default(),
+        hostname,
+        domain_listed_in: domain_listed_info,
+        feed_listed_in: feed_listed_info,
+        abuse_contact,
+    };
+    score(&mut record, &state.blocklist);
+    record
+}
+
+// Sums weight times severity over every listing, capped at 100, and derives the verdict
+pub fn score(record: &mut BlocklistRecord, config: &BlocklistConfig) {
+    let weight = |list: &str| config.weights.get(list).copied().unwrap_or(1.0);
+    let dnsbl_score: f64 = record
+        .listed_in
+        .iter()
+        .chain(&record.domain_listed_in)
+        .map(|entry| {
+            let severity = config
+                .severities
+                .get(&format!("{:?}", entry.reason))
+                .copied()
+                .unwrap_or_else(|| entry.reason.default_severity());
+            weight(&entry.dnsbl) * severity
+        })
+        .sum();
+    let feed_score: f64 = record
+        .feed_listed_in
+        .iter()
+        .map(|listing| {
+            let severity = config
+                .severities
+                .get(&listing.category)
+                .copied()
+                .unwrap_or(config.feed_severity);
+            weight(&listing.feed) * severity
+        })
+        .sum();
+
+    record.score = (dnsbl_score + feed_score).round().min(100.0) as u32;
+    record.verdict = if record.score >= config.bad_score {
+        "bad"
+    } else if record.score >= config.suspicious_score {
+        "suspicious"
+    } else {
+        "clean"
     }
+    .to_string();
 }
 
 pub async fn check_blocklists(ip: &IpAddr, resolver: &DnsResolver) -> Vec<BlocklistEntry> {
-    let mut tasks = Vec::new();
+    collect_listings(spawn_blocklist_checks(ip, resolver), BLOCKLISTS).await
+}
+
+// Resolves the PTR name and checks its domain, addresses without one can't be listed
+async fn check_hostname(ip: IpAddr, resolver: DnsResolver) -> (String, Vec<BlocklistEntry>) {
+    let hostname = get_reverse_dns(ip, &resolver).await.unwrap_or_default();
+    let results = spawn_domain_blocklist_checks(&hostname, &resolver);
+    (hostname, collect_listings(results, DOMAIN_BLOCKLISTS).await)
+}
+
+async fn collect_listings(
+    mut results: mpsc::UnboundedReceiver<(&'static str, Option<BlocklistEntry>)>,
+    lists: &[&str],
+) -> Vec<BlocklistEntry> {
     let mut listed_in: Vec<BlocklistEntry> = Vec::new();
 
+    while let Some((_, entry)) = results.recv().await {
+        listed_in.extend(entry);
+    }
+
+    // Results arrive in completion order, keep the output stable
+    listed_in.sort_by_key(|entry| lists.iter().position(|&dnsbl| dnsbl == entry.dnsbl));
+    listed_in
+}
+
+// Queries every DNSBL concurrently and yields `(dnsbl, listing)` pairs as the lookups finish.
+// Non-global addresses can't be listed, nothing is queried for them
+pub fn spawn_blocklist_checks(
+    ip: &IpAddr,
+    resolver: &DnsResolver,
+) -> mpsc::UnboundedReceiver<(&'static str, Option<BlocklistEntry>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    if !is_global(*ip) {
+        return rx;
+    }
+
     if let IpAddr::V4(addr) = ip {
         let reversed_ip = addr
             .octets()
@@ -52,39 +155,96 @@ pub async fn check_blocklists(ip: &IpAddr, resolver: &DnsResolver) -> Vec<Blockl
             .collect::<Vec<_>>()
             .join(".");
 
-        for &dnsbl in BLOCKLISTS {
-            let query = format!("{}.{}", reversed_ip, dnsbl);
-            let resolver = resolver.clone();
+        spawn_queries(&reversed_ip, BLOCKLISTS, resolver, &tx);
+    }
 
-            tasks.push(tokio::spawn(async move {
-                (dnsbl, resolver.lookup_blocklist(query).await)
-            }));
-        }
+    rx
+}
 
-        for task in tasks {
-            if let Ok((dnsbl, Some(addr))) = task.await {
-                let reason = BlocklistReason::from(
-                    dnsbl.to_string().as_str(),
-                    addr.to_string().as_str(),
-                );
+// Same as `spawn_blocklist_checks` for the domain blocklists, nothing is queried for hostnames
+// without a registered domain
+pub fn spawn_domain_blocklist_checks(
+    hostname: &str,
+    resolver: &DnsResolver,
+) -> mpsc::UnboundedReceiver<(&'static str, Option<BlocklistEntry>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
 
-                if reason == BlocklistReason::Unknown {
-                    continue;
-                }
+    if let Some(domain) = registered_domain(hostname) {
+        spawn_queries(&domain, DOMAIN_BLOCKLISTS, resolver, &tx);
+    }
 
-                listed_in.push(BlocklistEntry {
+    rx
+}
+
+fn spawn_queries(
+    name: &str,
+    lists: &'static [&'static str],
+    resolver: &DnsResolver,
+    tx: &mpsc::UnboundedSender<(&'static str, Option<BlocklistEntry>)>,
+) {
+    for &dnsbl in lists {
+        let query = format!("{}.{}", name, dnsbl);
+        let resolver = resolver.clone();
+        let tx = tx.clone();
+
+        tokio::spawn(async move {
+            let entry = resolver
+                .lookup_blocklist(dnsbl, query)
+                .await
+                .map(|addr| BlocklistReason::from(dnsbl, addr.to_string().as_str()))
+                .filter(|reason| *reason != BlocklistReason::Unknown)
+                .map(|reason| BlocklistEntry {
                     dnsbl: dnsbl.to_string(),
                     reason,
                 });
-            }
-        }
+
+            let _ = tx.send((dnsbl, entry));
+        });
     }
+}
 
-    listed_in
+// Domain lists only contain registered domains, `mail.example.co.uk` is checked as
+// `example.co.uk`. A heuristic, the public suffix list isn't available here
+pub fn registered_domain(hostname: &str) -> Option<String> {
+    let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+    let labels: Vec<&str> = hostname.split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|label| label.is_empty()) {
+        return None;
+    }
+
+    let tld = labels[labels.len() - 1];
+    let second_level = labels[labels.len() - 2];
+    let count = if tld.len() == 2 && SECOND_LEVEL_LABELS.contains(&second_level) {
+        3
+    } else {
+        2
+    };
+    if labels.len() < count {
+        return None;
+    }
+    Some(labels[labels.len() - count..].join("."))
 }
 
 impl BlocklistReason {
+    // Used for reasons missing from `blocklist.severities`. Dynamic ranges are listed for
+    // policy rather than abuse and barely count
+    pub fn default_severity(&self) -> f64 {
+        match self {
+            BlocklistReason::Malware => 80.0,
+            BlocklistReason::Phishing => 70.0,
+            BlocklistReason::ExploitedOrMalicious => 60.0,
+            BlocklistReason::SpamSource => 50.0,
+            BlocklistReason::SpamSupport => 25.0,
+            BlocklistReason::DynamicResidential => 10.0,
+            BlocklistReason::Unknown => 0.0,
+        }
+    }
+
     pub fn from(provider: &str, response: &str) -> Self {
+        if let Some(reason) = bitmask_reason(provider, response) {
+            return reason;
+        }
+
         let mappings: HashMap<&str, HashMap<&str, BlocklistReason>> = HashMap::from([
             (
                 "zen.spamhaus.org",
@@ -109,6 +269,21 @@ impl BlocklistReason {
                     ("127.0.0.10", BlocklistReason::DynamicResidential),
                 ]),
             ),
+            // 127.0.1.1xx are legitimate domains that have been compromised
+            (
+                "dbl.spamhaus.org",
+                HashMap::from([
+                    ("127.0.1.2", BlocklistReason::SpamSource),
+                    ("127.0.1.4", BlocklistReason::Phishing),
+                    ("127.0.1.5", BlocklistReason::Malware),
+                    ("127.0.1.6", BlocklistReason::Malware),
+                    ("127.0.1.102", BlocklistReason::ExploitedOrMalicious),
+                    ("127.0.1.103", BlocklistReason::SpamSupport),
+                    ("127.0.1.104", BlocklistReason::Phishing),
+                    ("127.0.1.105", BlocklistReason::Malware),
+                    ("127.0.1.106", BlocklistReason::Malware),
+                ]),
+            ),
         ]);
 
         mappings
@@ -118,3 +293,161 @@ impl BlocklistReason {
             .unwrap_or(BlocklistReason::Unknown)
     }
 }
+
+// SURBL and URIBL combine their sublists as bits of the last octet, the most severe one wins.
+// 127.0.0.1 means the query was refused
+fn bitmask_reason(provider: &str, response: &str) -> Option<BlocklistReason> {
+    let bits: &[(u8, BlocklistReason)] = match provider {
+        "multi.surbl.org" => &[
+            (16, BlocklistReason::Malware),
+            (8, BlocklistReason::Phishing),
+            (128, BlocklistReason::ExploitedOrMalicious),
+            (64, BlocklistReason::SpamSource),
+        ],
+        "multi.uribl.com" => &[
+            (2, BlocklistReason::SpamSource),
+            (8, BlocklistReason::SpamSource),
+            (4, BlocklistReason::SpamSupport),
+        ],
+        _ => return None,
+    };
+
+    let octets = response.parse::<Ipv4Addr>().ok()?.octets();
+    if octets[..3] != [127, 0, 0] {
+        return Some(BlocklistReason::Unknown);
+    }
+    let reason = bits
+        .iter()
+        .find(|(bit, _)| octets[3] & bit != 0)
+        .map(|(_, reason)| reason.clone())
+        .unwrap_or(BlocklistReason::Unknown);
+    Some(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FeedListing;
+
+    fn record(listed_in: &[(&str, BlocklistReason)], feeds: &[(&str, &str)]) -> BlocklistRecord {
+        BlocklistRecord {
+            ip: "192.0.2.1".to_string(),
+            blocked: true,
+            score: 0,
+            verdict: String::new(),
+            listed_in: listed_in
+                .iter()
+                .map(|(dnsbl, reason)| BlocklistEntry {
+                    dnsbl: dnsbl.to_string(),
+                    reason: reason.clone(),
+                })
+                .collect(),
+            hostname: String::new(),
+            domain: String::new(),
+            domain_listed_in: Vec::new(),
+            feed_listed_in: feeds
+                .iter()
+                .map(|(feed, category)| FeedListing {
+                    feed: feed.to_string(),
+                    category: category.to_string(),
+                    network: "192.0.2.0/24".to_string(),
+                })
+                .collect(),
+            abuse_contact: None,
+        }
+    }
+
+    fn scored(mut record: BlocklistRecord, config: &BlocklistConfig) -> (u32, String) {
+        score(&mut record, config);
+        (record.score, record.verdict)
+    }
+
+    #[test]
+    fn unlisted_addresses_are_clean() {
+        let config = BlocklistConfig::default();
+        assert_eq!(scored(record(&[], &[]), &config), (0, "clean".to_string()));
+    }
+
+    #[test]
+    fn listings_add_up_to_the_verdict() {
+        let config = BlocklistConfig::default();
+        let spam = record(&[("zen.spamhaus.org", BlocklistReason::SpamSupport)], &[]);
+        assert_eq!(scored(spam, &config), (25, "suspicious".to_string()));
+
+        let spam = record(&[("zen.spamhaus.org", BlocklistReason::SpamSource)], &[]);
+        assert_eq!(scored(spam, &config), (50, "bad".to_string()));
+
+        let dynamic = record(
+            &[("zen.spamhaus.org", BlocklistReason::DynamicResidential)],
+            &[],
+        );
+        assert_eq!(scored(dynamic, &config), (10, "clean".to_string()));
+    }
+
+    #[test]
+    fn scores_are_capped_at_100() {
+        let config = BlocklistConfig::default();
+        let listed = record(
+            &[
+                ("zen.spamhaus.org", BlocklistReason::ExploitedOrMalicious),
+                ("dbl.spamhaus.org", BlocklistReason::Malware),
+            ],
+            &[("drop", "hijacked")],
+        );
+        assert_eq!(scored(listed, &config), (100, "bad".to_string()));
+    }
+
+    #[test]
+    fn weights_and_severities_override_the_defaults() {
+        let config = BlocklistConfig {
+            weights: HashMap::from([("bl.spamcop.net".to_string(), 0.5)]),
+            severities: HashMap::from([
+                ("DynamicResidential".to_string(), 0.0),
+                ("hijacked".to_string(), 80.0),
+            ]),
+            ..BlocklistConfig::default()
+        };
+
+        let spam = record(&[("bl.spamcop.net", BlocklistReason::SpamSource)], &[]);
+        assert_eq!(scored(spam, &config), (25, "suspicious".to_string()));
+
+        let dynamic = record(
+            &[("zen.spamhaus.org", BlocklistReason::DynamicResidential)],
+            &[],
+        );
+        assert_eq!(scored(dynamic, &config), (0, "clean".to_string()));
+
+        let hijacked = record(&[], &[("drop", "hijacked")]);
+        assert_eq!(scored(hijacked, &config), (80, "bad".to_string()));
+    }
+
+    #[test]
+    fn registered_domain_of_hostnames() {
+        let domain = |hostname| registered_domain(hostname).unwrap_or_default();
+        assert_eq!(domain("mail.example.com"), "example.com");
+        assert_eq!(domain("example.com."), "example.com");
+        assert_eq!(domain("A.B.Example.DE"), "example.de");
+        assert_eq!(domain("mail.example.co.uk"), "example.co.uk");
+        assert_eq!(domain("host.example.com.au"), "example.com.au");
+        // Only two letter country codes have second level registries
+        assert_eq!(domain("mail.example.co.com"), "co.com");
+    }
+
+    #[test]
+    fn registered_domain_of_invalid_hostnames() {
+        assert_eq!(registered_domain(""), None);
+        assert_eq!(registered_domain("localhost"), None);
+        assert_eq!(registered_domain("co.uk"), None);
+        assert_eq!(registered_domain("mail..example.com"), None);
+    }
+
+    #[test]
+    fn feeds_without_a_severity_use_feed_severity() {
+        let config = BlocklistConfig {
+            feed_severity: 30.0,
+            ..BlocklistConfig::default()
+        };
+        let listed = record(&[], &[("internal", "scanner")]);
+        assert_eq!(scored(listed, &config), (30, "suspicious".to_string()));
+    }
+}
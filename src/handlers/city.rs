@@ -1,4 +1,5 @@
 use crate::format_middleware::Format;
+use crate::metrics;
 use crate::models::SimpleResponse;
 use crate::util::{format_response, get_ip};
 use crate::AppState;
@@ -15,9 +16,8 @@ pub async fn city_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) ->
     )
 }
 
-pub fn get_city(ip: IpAddr, geo_db: &Reader<Vec<u8>>) -> Option<String> {
-    geo_db
-        .lookup::<geoip2::City>(ip)
+pub fn get_city<S: AsRef<[u8]>>(ip: IpAddr, geo_db: &Reader<S>) -> Option<String> {
+    metrics::observe_mmdb_lookup("geo", geo_db.lookup::<geoip2::City>(ip))
         .ok()?
         .city?
         .names?
@@ -27,6 +27,6 @@ pub fn get_city(ip: IpAddr, geo_db: &Reader<Vec<u8>>) -> Option<String> {
 }
 
 pub fn get_city_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> SimpleResponse {
-    let result = get_city(get_ip(&req), &state.geo_db).unwrap_or_else(|| "".to_string());
+    let result = get_city(get_ip(req), &state.geo_db.get()).unwrap_or_default();
     SimpleResponse { value: result }
 }
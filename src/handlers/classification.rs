@@ -0,0 +1,17 @@
+use crate::format_middleware::Format;
+use crate::models::AddressClassification;
+use crate::special_purpose::classify;
+use crate::util::{format_response, get_ip};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+
+pub async fn classification_handler(req: HttpRequest) -> HttpResponse {
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_classification_response(&req),
+        false,
+    )
+}
+
+pub fn get_classification_response(req: &HttpRequest) -> AddressClassification {
+    classify(get_ip(req))
+}
@@ -0,0 +1,290 @@
+use crate::format_middleware::Format;
+use crate::models::{ConvertResponse, SubnetResponse};
+use crate::util::format_response;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub async fn convert_handler(req: HttpRequest, ip: web::Path<String>) -> HttpResponse {
+    match ip.parse::<IpAddr>() {
+        Ok(ip) => format_response(
+            req.extensions().get::<Format>().unwrap(),
+            &convert(ip),
+            false,
+        ),
+        Err(_) => HttpResponse::BadRequest().body("Invalid IP address\n"),
+    }
+}
+
+pub async fn subnet_handler(req: HttpRequest, path: web::Path<(String, String)>) -> HttpResponse {
+    let (network, prefix) = path.into_inner();
+    match parse_subnet(&network, &prefix) {
+        Some(network) => format_response(
+            req.extensions().get::<Format>().unwrap(),
+            &subnet(network),
+            false,
+        ),
+        None => HttpResponse::BadRequest().body("Invalid network\n"),
+    }
+}
+
+pub fn convert(ip: IpAddr) -> ConvertResponse {
+    let (version, value, bits) = match ip {
+        IpAddr::V4(ip) => (4, u128::from(u32::from(ip)), 32),
+        IpAddr::V6(ip) => (6, u128::from(ip), 128),
+    };
+    let ipv6 = match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+
+    ConvertResponse {
+        ip: ip.to_string(),
+        version,
+        decimal: value.to_string(),
+        hex: format!("0x{:0width$x}", value, width = bits / 4),
+        binary: binary(value, bits),
+        ipv4: embedded_ipv4(ip)
+            .map(|ip| ip.to_string())
+            .unwrap_or_default(),
+        ipv4_mapped: match ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped().to_string(),
+            IpAddr::V6(_) => "".to_string(),
+        },
+        six_to_four: match ip {
+            IpAddr::V4(ip) => six_to_four(ip).to_string(),
+            IpAddr::V6(_) => "".to_string(),
+        },
+        reverse_dns: reverse_dns_name(ip),
+        expanded: ipv6
+            .segments()
+            .iter()
+            .map(|segment| format!("{:04x}", segment))
+            .collect::<Vec<String>>()
+            .join(":"),
+        compressed: ipv6.to_string(),
+    }
+}
+
+pub fn subnet(network: IpNet) -> SubnetResponse {
+    let network = network.trunc();
+    let host_bits = u32::from(network.max_prefix_len() - network.prefix_len());
+
+    let mut hosts = network.hosts();
+    let first_host = hosts.next();
+    let last_host = hosts.next_back().or(first_host);
+    let usable = match network {
+        // The network and broadcast addresses aren't usable, except in /31 and /32 networks
+        IpNet::V4(_) if host_bits >= 2 => ((1u64 << host_bits) - 2).to_string(),
+        _ => count_string(host_bits),
+    };
+
+    SubnetResponse {
+        cidr: network.to_string(),
+        version: if network.addr().is_ipv4() { 4 } else { 6 },
+        network: network.network().to_string(),
+        broadcast: match network {
+            IpNet::V4(network) => network.broadcast().to_string(),
+            IpNet::V6(_) => "".to_string(),
+        },
+        netmask: network.netmask().to_string(),
+        wildcard: network.hostmask().to_string(),
+        prefix_len: network.prefix_len(),
+        first_host: first_host.map(|ip| ip.to_string()).unwrap_or_default(),
+        last_host: last_host.map(|ip| ip.to_string()).unwrap_or_default(),
+        addresses: count_string(host_bits),
+        hosts: usable,
+    }
+}
+
+// `prefix` is a prefix length or, for IPv4, a dotted netmask
+fn parse_subnet(network: &str, prefix: &str) -> Option<IpNet> {
+    let address = network.parse::<IpAddr>().ok()?;
+    if let Ok(prefix_len) = prefix.parse::<u8>() {
+        return IpNet::new(address, prefix_len).ok();
+    }
+    match (address, prefix.parse::<Ipv4Addr>().ok()?) {
+        (IpAddr::V4(address), netmask) => {
+            Ipv4Net::with_netmask(address, netmask).ok().map(IpNet::V4)
+        }
+        (IpAddr::V6(_), _) => None,
+    }
+}
+
+// 2^bits as a decimal string, an IPv6 /0 doesn't fit into a u128
+fn count_string(bits: u32) -> String {
+    match 1u128.checked_shl(bits) {
+        Some(count) => count.to_string(),
+        None => "340282366920938463463374607431768211456".to_string(),
+    }
+}
+
+// Bits in octets (IPv4) or 16 bit groups (IPv6)
+fn binary(value: u128, bits: usize) -> String {
+    let (group, separator) = if bits == 32 { (8, ".") } else { (16, ":") };
+    let digits = format!("{:0width$b}", value, width = bits);
+    digits
+        .as_bytes()
+        .chunks(group)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+fn embedded_ipv4(ip: IpAddr) -> Option<Ipv4Addr> {
+    match ip {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            match segments[0] {
+                0x2002 => Some(Ipv4Addr::from(
+                    (u32::from(segments[1]) << 16) | u32::from(segments[2]),
+                )),
+                _ => ip.to_ipv4_mapped(),
+            }
+        }
+    }
+}
+
+// The /48 a 6to4 router derives from its IPv4 address (RFC 3056)
+fn six_to_four(ip: Ipv4Addr) -> Ipv6Net {
+    let octets = ip.octets();
+    let address = Ipv6Addr::new(
+        0x2002,
+        u16::from_be_bytes([octets[0], octets[1]]),
+        u16::from_be_bytes([octets[2], octets[3]]),
+        0,
+        0,
+        0,
+        0,
+        0,
+    );
+    Ipv6Net::new(address, 48).unwrap()
+}
+
+fn reverse_dns_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            format!(
+                "{}.{}.{}.{}.in-addr.arpa",
+                octets[3], octets[2], octets[1], octets[0]
+            )
+        }
+        IpAddr::V6(ip) => {
+            let nibbles = format!("{:032x}", u128::from(ip));
+            let reversed = nibbles
+                .chars()
+                .rev()
+                .map(|nibble| nibble.to_string())
+                .collect::<Vec<String>>()
+                .join(".");
+            format!("{}.ip6.arpa", reversed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(network: &str, prefix: &str) -> IpNet {
+        parse_subnet(network, prefix).unwrap()
+    }
+
+    #[test]
+    fn parse_subnet_accepts_prefix_lengths_and_netmasks() {
+        assert_eq!(parse("10.0.0.0", "8"), "10.0.0.0/8".parse().unwrap());
+        assert_eq!(
+            parse("10.1.2.3", "255.255.0.0"),
+            "10.1.2.3/16".parse().unwrap()
+        );
+        assert_eq!(parse("2001:db8::", "32"), "2001:db8::/32".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_subnet_rejects_invalid_input() {
+        assert_eq!(parse_subnet("10.0.0.0", "33"), None);
+        assert_eq!(parse_subnet("2001:db8::", "129"), None);
+        assert_eq!(parse_subnet("2001:db8::", "255.255.0.0"), None);
+        assert_eq!(parse_subnet("10.0.0.0", "255.0.255.0"), None);
+        assert_eq!(parse_subnet("10.0.0", "8"), None);
+    }
+
+    #[test]
+    fn subnet_of_ipv4_network() {
+        let result = subnet(parse("192.0.2.77", "24"));
+        assert_eq!(result.cidr, "192.0.2.0/24");
+        assert_eq!(result.broadcast, "192.0.2.255");
+        assert_eq!(result.netmask, "255.255.255.0");
+        assert_eq!(result.wildcard, "0.0.0.255");
+        assert_eq!(result.first_host, "192.0.2.1");
+        assert_eq!(result.last_host, "192.0.2.254");
+        assert_eq!(result.addresses, "256");
+        assert_eq!(result.hosts, "254");
+    }
+
+    #[test]
+    fn subnet_of_point_to_point_and_host_routes() {
+        let result = subnet(parse("192.0.2.0", "31"));
+        assert_eq!(result.first_host, "192.0.2.0");
+        assert_eq!(result.last_host, "192.0.2.1");
+        assert_eq!(result.addresses, "2");
+        assert_eq!(result.hosts, "2");
+
+        let result = subnet(parse("192.0.2.1", "32"));
+        assert_eq!(result.first_host, "192.0.2.1");
+        assert_eq!(result.last_host, "192.0.2.1");
+        assert_eq!(result.addresses, "1");
+        assert_eq!(result.hosts, "1");
+    }
+
+    #[test]
+    fn subnet_of_whole_address_space() {
+        let result = subnet(parse("0.0.0.0", "0"));
+        assert_eq!(result.broadcast, "255.255.255.255");
+        assert_eq!(result.addresses, "4294967296");
+        assert_eq!(result.hosts, "4294967294");
+
+        let result = subnet(parse("::", "0"));
+        assert_eq!(result.addresses, "340282366920938463463374607431768211456");
+        assert_eq!(result.hosts, "340282366920938463463374607431768211456");
+    }
+
+    #[test]
+    fn subnet_of_ipv6_network() {
+        let result = subnet(parse("2001:db8::1", "64"));
+        assert_eq!(result.cidr, "2001:db8::/64");
+        assert_eq!(result.broadcast, "");
+        assert_eq!(result.addresses, "18446744073709551616");
+        assert_eq!(result.hosts, "18446744073709551616");
+
+        let result = subnet(parse("2001:db8::1", "128"));
+        assert_eq!(result.addresses, "1");
+        assert_eq!(result.hosts, "1");
+    }
+
+    #[test]
+    fn binary_groups_octets_and_hextets() {
+        assert_eq!(
+            binary(u128::from(u32::from(Ipv4Addr::new(192, 0, 2, 1))), 32),
+            "11000000.00000000.00000010.00000001"
+        );
+        let ipv6 = binary(u128::from("2001:db8::1".parse::<Ipv6Addr>().unwrap()), 128);
+        assert_eq!(ipv6.split(':').count(), 8);
+        assert!(ipv6.starts_with("0010000000000001:0000110110111000:0000000000000000:"));
+        assert!(ipv6.ends_with(":0000000000000001"));
+    }
+
+    #[test]
+    fn reverse_dns_names() {
+        assert_eq!(
+            reverse_dns_name("192.0.2.1".parse().unwrap()),
+            "1.2.0.192.in-addr.arpa"
+        );
+        assert_eq!(
+            reverse_dns_name("2001:db8::567:89ab".parse().unwrap()),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::format_middleware::Format;
+use crate::metrics;
 use crate::models::SimpleResponse;
 use crate::util::{format_response, get_ip};
 use crate::AppState;
@@ -18,9 +19,8 @@ pub async fn country_code_handler(
     )
 }
 
-pub fn get_country_code(ip: IpAddr, geo_db: &Reader<Vec<u8>>) -> Option<String> {
-    geo_db
-        .lookup::<geoip2::City>(ip)
+pub fn get_country_code<S: AsRef<[u8]>>(ip: IpAddr, geo_db: &Reader<S>) -> Option<String> {
+    metrics::observe_mmdb_lookup("geo", geo_db.lookup::<geoip2::City>(ip))
         .ok()?
         .country?
         .iso_code
@@ -31,6 +31,11 @@ pub fn get_country_code_response(
     req: &HttpRequest,
     state: &web::Data<Arc<AppState>>,
 ) -> SimpleResponse {
-    let result = get_country_code(get_ip(&req), &state.geo_db).unwrap_or_else(|| "".to_string());
+    let ip = get_ip(req);
+    let result = state
+        .network
+        .country_code(ip)
+        .or_else(|| get_country_code(ip, &state.geo_db.get()))
+        .unwrap_or_default();
     SimpleResponse { value: result }
 }
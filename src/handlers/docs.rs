@@ -1,49 +1,29 @@
 use crate::format_middleware::Format;
 use crate::format_middleware::Format::Plain;
-use crate::handlers::all::get_all_response;
-use crate::handlers::asn::get_asn_response;
-use crate::handlers::blocklist::get_blocklist_response;
-use crate::handlers::city::get_city_response;
-use crate::handlers::country::get_country_response;
-use crate::handlers::country_code::get_country_code_response;
-use crate::handlers::ip::get_ip_response;
-use crate::handlers::region::get_region_response;
-use crate::handlers::reverse_dns::get_reverse_dns_response;
+use crate::handlers::country::get_country;
+use crate::handlers::reverse_dns::get_reverse_dns;
+use crate::handlers::{EndpointBody, ENDPOINTS};
 use crate::models::{ToCsv, ToPlainText};
-use crate::util::{client_supports_color, format_response};
+use crate::streaming;
+use crate::util::{client_supports_color, format_response, get_ip};
 use crate::AppState;
 use actix_web::body::MessageBody;
+use actix_web::web::Bytes;
 use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use comfy_table::{Attribute, Cell, Color, ContentArrangement, Table, TableComponent};
 use serde::Serialize;
 use std::sync::Arc;
 
-macro_rules! add_row {
-    ($table:expr, $endpoint:expr, $resp:expr, $format:expr, $f:expr) => {
-        $table.add_row(vec![
-            Cell::new("curl").fg(Color::Red),
-            Cell::new(endpoint_url($endpoint, $format))
-                .fg(Color::Cyan)
-                .add_attribute(Attribute::Bold),
-            Cell::new($f($format, &$resp)).fg(Color::DarkYellow),
-        ])
-    };
-}
-
 fn endpoint_url(endpoint: &str, format: &Format) -> String {
     if *format == Plain {
-        format!("ipeek.io{}", endpoint)
+        format!("ipeek.io{}", endpoint.trim_end_matches('/'))
     } else {
         format!("ipeek.io{}.{}", endpoint, format)
     }
 }
 
 pub async fn docs_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
-    let info = get_all_response(&req, &state).await;
-
-    let ip_address = info.ip;
-    let remote_host = info.reverse_dns;
-    let country_code = info.country;
+    let ip = get_ip(&req);
 
     let (green, yellow, magenta, red, cyan, reset, bold, highlight) = if client_supports_color(&req) {
         (
@@ -74,13 +54,31 @@ pub async fn docs_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) ->
         reset = reset
     );
 
-    let doc = format!(
+    // The header is sent right away, the parts waiting on DNS lookups follow as they are ready
+    let head = format!(
         r#"{ascii_art}
 
 ---------------
 IP Address:        {yellow}{ip_address}{reset}
-Remote Host:       {yellow}{remote_host}{reset}
-Country:           {yellow}{country_code}{reset}
+"#,
+        ascii_art = ascii_art,
+        ip_address = ip,
+        yellow = yellow,
+        reset = reset
+    );
+
+    let (tx, body) = streaming::channel();
+    let _ = tx.send(Bytes::from(head));
+
+    actix_web::rt::spawn(async move {
+        let remote_host = get_reverse_dns(ip, &state.dns_resolver)
+            .await
+            .unwrap_or_default();
+        let country = get_country(ip, &state.geo_db.get()).unwrap_or_default();
+
+        let overview = format!(
+            r#"Remote Host:       {yellow}{remote_host}{reset}
+Country:           {yellow}{country}{reset}
 
 {magenta}{bold}Output Formats{reset}
 --------------
@@ -92,35 +90,47 @@ You can request different formats by appending the desired extension to the endp
   - {highlight}.csv{reset}      → Returns data in CSV format
   - {highlight}.yaml{reset}     → Returns data in YAML format
   - {highlight}.msgpack{reset}  → Returns data in MessagePack (binary) format
+  - {highlight}.env{reset}      → Returns shell variables (IPEEK_*) safe to eval
+  - {highlight}.sse{reset}      → Returns Server-Sent Events, /all streams results as they arrive
+
+Append {highlight}?callback=fn{reset} to wrap the JSON response in a JSONP callback.
+Plain-text responses of slow endpoints like {cyan}/all{reset} are streamed as the lookups finish.
 
 {magenta}{bold}Examples:{reset}
   {red}curl {bold}{cyan}ipeek.io/ip{reset}          # Plain text
   {red}curl {bold}{cyan}ipeek.io/ip{highlight}.json{reset}     # JSON
   {red}curl {bold}{cyan}ipeek.io/ip{highlight}.xml{reset}      # XML
   {red}curl {bold}{cyan}ipeek.io/ip{highlight}.csv{reset}      # CSV
+  {red}eval "$(curl -s {bold}{cyan}ipeek.io/all{highlight}.env{reset})"  # Shell variables
 
 {magenta}{bold}IPv4/IPv6 Forcing{reset}
 -------------------------------------
 You can force an IPv4 connection by using the subdomain {highlight}4.{reset}{cyan}ipeek.io{reset}
 and force an IPv6 connection by using {highlight}6.{reset}{cyan}ipeek.io{reset}.
+{red}curl {bold}{cyan}ipeek.io/dual{reset} returns a token and a URL that adds your other address.
 
 {magenta}{bold}Enpoints{reset}
 -------------------------------------
-{curl_request_table}
-
 "#,
-        ip_address = ip_address,
-        curl_request_table = curl_request_table(req, state).await.as_str(),
-        ascii_art = ascii_art,
-        yellow = yellow,
-        magenta = magenta,
-        cyan = cyan,
-        reset = reset,
-        bold = bold,
-        highlight = highlight
-    );
+            remote_host = remote_host,
+            country = country,
+            yellow = yellow,
+            magenta = magenta,
+            red = red,
+            cyan = cyan,
+            reset = reset,
+            bold = bold,
+            highlight = highlight
+        );
+        if tx.send(Bytes::from(overview)).is_err() {
+            return;
+        }
+
+        let table = curl_request_table(req, state).await;
+        let _ = tx.send(Bytes::from(format!("{}\n\n", table)));
+    });
 
-    HttpResponse::Ok().content_type("text/plain").body(doc)
+    HttpResponse::Ok().content_type("text/plain").body(body)
 }
 
 async fn curl_request_table(req: HttpRequest, state: web::Data<Arc<AppState>>) -> String {
@@ -140,66 +150,23 @@ async fn curl_request_table(req: HttpRequest, state: web::Data<Arc<AppState>>) -
         .set_content_arrangement(ContentArrangement::Dynamic)
         .set_style(TableComponent::VerticalLines, ' ');
 
-    add_row!(
-        table,
-        if format == Plain { "" } else { "/" },
-        get_ip_response(&req),
-        &format,
-        f
-    );
-    add_row!(table, "/ip", get_ip_response(&req), &format, f);
-    add_row!(
-        table,
-        "reverse_dns",
-        get_reverse_dns_response(&req, &state).await,
-        &format,
-        f
-    );
-    add_row!(
-        table,
-        "/country",
-        get_country_response(&req, &state),
-        &format,
-        f
-    );
-    add_row!(
-        table,
-        "/country_code",
-        get_country_code_response(&req, &state),
-        &format,
-        f
-    );
-    add_row!(
-        table,
-        "/region",
-        get_region_response(&req, &state),
-        &format,
-        f
-    );
-    add_row!(table, "/city", get_city_response(&req, &state), &format, f);
-    add_row!(table, "/asn", get_asn_response(&req, &state), &format, f);
-    add_row!(
-        table,
-        "/all",
-        get_all_response(&req, &state).await,
-        &format,
-        f
-    );
-    add_row!(
-        table,
-        "/blocklist",
-        get_blocklist_response(&req, &state).await,
-        &format,
-        f
-    );
+    for endpoint in ENDPOINTS {
+        let (url, example) = match &endpoint.body {
+            EndpointBody::Model { example, .. } => (
+                endpoint_url(endpoint.path, &format),
+                example(req.clone(), state.clone(), format.clone()).await,
+            ),
+            EndpointBody::Document { description, .. } => {
+                (endpoint_url(endpoint.path, &Plain), description.to_string())
+            }
+        };
 
-    table.add_row(vec![
-        Cell::new("curl").fg(Color::Red),
-        Cell::new("ipeek.io/docs")
-            .fg(Color::Cyan)
-            .add_attribute(Attribute::Bold),
-        Cell::new("(Documentation in plain-text format)").fg(Color::DarkYellow),
-    ]);
+        table.add_row(vec![
+            Cell::new("curl").fg(Color::Red),
+            Cell::new(url).fg(Color::Cyan).add_attribute(Attribute::Bold),
+            Cell::new(example).fg(Color::DarkYellow),
+        ]);
+    }
 
     if client_supports_color(&req) {
         table.enforce_styling();
@@ -209,7 +176,7 @@ async fn curl_request_table(req: HttpRequest, state: web::Data<Arc<AppState>>) -
     table.to_string()
 }
 
-fn f<T, U>(format: &Format, response: &T) -> String
+pub fn render_example<T, U>(format: &Format, response: &T) -> String
 where
     T: Serialize + ToPlainText + ToCsv<U> + yaserde::YaSerialize,
     U: Serialize,
@@ -0,0 +1,78 @@
+use crate::address_family::{AddressFamily, DualStackEntry};
+use crate::format_middleware::Format;
+use crate::models::DualStackResponse;
+use crate::util::{format_response, get_ip};
+use crate::AppState;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+pub async fn dual_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
+    let (token, entry) = match state.address_families.create_token(get_ip(&req)) {
+        Some(token) => token,
+        None => {
+            return HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", "5"))
+                .body("Too many pending tokens, try again later\n");
+        }
+    };
+
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_dual_response(&req, &state, token, entry),
+        false,
+    )
+}
+
+pub async fn dual_token_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    token: web::Path<String>,
+) -> HttpResponse {
+    let token = token.into_inner();
+    let entry = match state.address_families.complete_token(&token, get_ip(&req)) {
+        Some(entry) => entry,
+        None => return HttpResponse::NotFound().body("Unknown or expired token\n"),
+    };
+
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_dual_response(&req, &state, token, entry),
+        false,
+    )
+}
+
+fn get_dual_response(
+    req: &HttpRequest,
+    state: &web::Data<Arc<AppState>>,
+    token: String,
+    entry: DualStackEntry,
+) -> DualStackResponse {
+    let missing = match (entry.ipv4, entry.ipv6) {
+        (Some(_), None) => Some(AddressFamily::V6),
+        (None, Some(_)) => Some(AddressFamily::V4),
+        _ => None,
+    };
+
+    // The follow-up has to hit the host of the missing family, otherwise the client would
+    // likely connect over the same family again
+    let next_url = missing
+        .and_then(|family| state.address_families.hostname(family))
+        .map(|hostname| {
+            format!(
+                "{}://{}/dual/{}",
+                req.connection_info().scheme(),
+                hostname,
+                token
+            )
+        })
+        .unwrap_or_default();
+
+    DualStackResponse {
+        token,
+        ipv4: entry.ipv4.map(|ip| ip.to_string()).unwrap_or_default(),
+        ipv6: entry.ipv6.map(|ip| ip.to_string()).unwrap_or_default(),
+        complete: missing.is_none(),
+        expires_in: entry.expires_in,
+        next_url,
+    }
+}
@@ -0,0 +1,98 @@
+use crate::format_middleware::Format;
+use crate::models::{DatabaseStatus, DnsStatus, ReadinessResponse, SimpleResponse};
+use crate::util::{format_epoch_date, format_response};
+use crate::AppState;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use maxminddb::{MaxMindDBError, Reader};
+use serde::de::IgnoredAny;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Covered by every GeoLite database
+const PROBE_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+
+pub async fn healthz_handler(req: HttpRequest) -> HttpResponse {
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_health_response(),
+        false,
+    )
+}
+
+pub async fn readyz_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
+    let info = get_readiness_response(&state).await;
+    let mut response = format_response(req.extensions().get::<Format>().unwrap(), &info, false);
+
+    if !info.ready && response.status().is_success() {
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    }
+
+    response
+}
+
+pub fn get_health_response() -> SimpleResponse {
+    SimpleResponse {
+        value: "ok".to_string(),
+    }
+}
+
+pub async fn get_readiness_response(state: &web::Data<Arc<AppState>>) -> ReadinessResponse {
+    let max_age_days = state.health.max_database_age_days;
+    let databases = vec![
+        get_database_status("geo", &state.geo_db.get(), max_age_days),
+        get_database_status("asn", &state.asn_db.get(), max_age_days),
+    ];
+
+    let (elapsed, outcome) = state.dns_resolver.probe().await;
+    let dns = DnsStatus {
+        canary_query: state.dns_resolver.canary_query().to_string(),
+        healthy: outcome.is_ok(),
+        duration_ms: elapsed.as_millis() as u64,
+        error: outcome.err().unwrap_or_default(),
+    };
+
+    ReadinessResponse {
+        ready: dns.healthy && databases.iter().all(|db| db.healthy),
+        databases,
+        dns,
+    }
+}
+
+fn get_database_status<S: AsRef<[u8]>>(
+    name: &str,
+    reader: &Reader<S>,
+    max_age_days: Option<u64>,
+) -> DatabaseStatus {
+    let metadata = &reader.metadata;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let age_days = now.saturating_sub(metadata.build_epoch) / 86_400;
+    let loaded = probe_lookup(reader);
+
+    DatabaseStatus {
+        name: name.to_string(),
+        loaded,
+        database_type: metadata.database_type.clone(),
+        build_epoch: metadata.build_epoch,
+        build_date: format_epoch_date(metadata.build_epoch),
+        age_days,
+        healthy: loaded && max_age_days.is_none_or(|max_age| age_days <= max_age),
+    }
+}
+
+// A lookup has to walk the search tree and decode the record it points to, which fails for
+// truncated or corrupt files. Unlisted addresses still prove the tree is intact
+fn probe_lookup<S: AsRef<[u8]>>(reader: &Reader<S>) -> bool {
+    match reader.lookup::<IgnoredAny>(PROBE_ADDRESS) {
+        Ok(_) | Err(MaxMindDBError::AddressNotFoundError(_)) => true,
+        Err(err) => {
+            let database_type = &reader.metadata.database_type;
+            log::warn!("Probe lookup in {} failed: {}", database_type, err);
+            false
+        }
+    }
+}
@@ -12,6 +12,6 @@ pub async fn ip_handler(req: HttpRequest) -> HttpResponse {
 }
 
 pub fn get_ip_response(req: &HttpRequest) -> SimpleResponse {
-    let ip = get_ip(&req).to_string();
+    let ip = get_ip(req).to_string();
     SimpleResponse { value: ip }
 }
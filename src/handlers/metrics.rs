@@ -0,0 +1,8 @@
+use crate::metrics;
+use actix_web::HttpResponse;
+
+pub async fn metrics_handler() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render())
+}
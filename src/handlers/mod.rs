@@ -1,38 +1,532 @@
+pub mod abuse;
+pub mod admin;
 pub mod all;
 pub mod asn;
 mod blocklist;
 pub mod city;
+pub mod classification;
+pub mod convert;
 pub mod country;
 pub mod country_code;
 pub mod docs;
+pub mod dual_stack;
+pub mod health;
 pub mod ip;
+pub mod metrics;
+pub mod network;
+pub mod openapi;
+pub mod port_check;
+pub mod rdap;
 pub mod region;
+pub mod request_info;
 pub mod reverse_dns;
 pub mod root;
+pub mod traceroute;
+pub mod user_agent;
 
-use crate::format_middleware::FormatMiddleware;
-use actix_web::web;
+use crate::api_keys::ApiKeyMiddleware;
+use crate::format_middleware::{Format, FormatMiddleware};
+use crate::handlers::docs::render_example;
+use crate::models::{
+    AbuseRecord, AddressClassification, AllResponse, AsnRecord, BlocklistRecord, ConvertResponse,
+    DualStackResponse, HeadersResponse, NetworkRecord, PortCheckResponse, RdapRecord,
+    ReadinessResponse, RequestResponse, SimpleResponse, SubnetResponse, TracerouteResponse,
+    UserAgentRecord,
+};
+use crate::rate_limit::{RateLimitMiddleware, RateLimitTier};
+use crate::util::get_ip;
+use crate::AppState;
+use actix_web::{web, HttpRequest, Route};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type ExampleFuture = Pin<Box<dyn Future<Output = String>>>;
+
+// What an endpoint responds with, used for the OpenAPI document and the docs table
+pub enum EndpointBody {
+    // A model rendered through `format_response`, available in every `Format`
+    Model {
+        schema: fn(&mut SchemaGenerator) -> Schema,
+        example: fn(HttpRequest, web::Data<Arc<AppState>>, Format) -> ExampleFuture,
+    },
+    // A fixed document with a single content type
+    Document {
+        content_type: &'static str,
+        description: &'static str,
+    },
+}
+
+pub struct Endpoint {
+    pub path: &'static str,
+    pub summary: &'static str,
+    // Whether the endpoint is served behind `FormatMiddleware` (extensions, Accept header)
+    pub formatted: bool,
+    pub rate_limit: RateLimitTier,
+    // Optional query parameters as (name, description), listed in the OpenAPI document
+    pub query: &'static [(&'static str, &'static str)],
+    pub body: EndpointBody,
+    pub route: fn() -> Route,
+}
+
+// Single source of truth for routing, `/docs` and `/openapi.json`
+pub static ENDPOINTS: &[Endpoint] = &[
+    Endpoint {
+        path: "/",
+        summary: "Caller IP address (documentation for browsers)",
+        formatted: true,
+        // Browsers are limited as `Expensive`, see `rate_limit::endpoint_tier`
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |req, _, format| {
+                Box::pin(async move { render_example(&format, &ip::get_ip_response(&req)) })
+            },
+        },
+        route: || web::get().to(root::root_handler),
+    },
+    Endpoint {
+        path: "/ip",
+        summary: "Caller IP address",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |req, _, format| {
+                Box::pin(async move { render_example(&format, &ip::get_ip_response(&req)) })
+            },
+        },
+        route: || web::get().to(ip::ip_handler),
+    },
+    Endpoint {
+        path: "/reverse_dns",
+        summary: "Reverse DNS (PTR) name of the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    let response = reverse_dns::get_reverse_dns_response(&req, &state).await;
+                    render_example(&format, &response)
+                })
+            },
+        },
+        route: || web::get().to(reverse_dns::reverse_dns_handler),
+    },
+    Endpoint {
+        path: "/country",
+        summary: "Country name of the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(&format, &country::get_country_response(&req, &state))
+                })
+            },
+        },
+        route: || web::get().to(country::country_handler),
+    },
+    Endpoint {
+        path: "/country_code",
+        summary: "ISO 3166-1 country code of the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(
+                        &format,
+                        &country_code::get_country_code_response(&req, &state),
+                    )
+                })
+            },
+        },
+        route: || web::get().to(country_code::country_code_handler),
+    },
+    Endpoint {
+        path: "/region",
+        summary: "Region (first subdivision) of the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(&format, &region::get_region_response(&req, &state))
+                })
+            },
+        },
+        route: || web::get().to(region::region_handler),
+    },
+    Endpoint {
+        path: "/city",
+        summary: "City of the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(&format, &city::get_city_response(&req, &state))
+                })
+            },
+        },
+        route: || web::get().to(city::city_handler),
+    },
+    Endpoint {
+        path: "/asn",
+        summary: "Autonomous system of the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<AsnRecord>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(&format, &asn::get_asn_response(&req, &state))
+                })
+            },
+        },
+        route: || web::get().to(asn::asn_handler),
+    },
+    Endpoint {
+        path: "/classification",
+        summary: "Special-purpose address range (RFC 6890) of the caller IP with its flags",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<AddressClassification>(),
+            example: |req, _, format| {
+                Box::pin(async move {
+                    render_example(&format, &classification::get_classification_response(&req))
+                })
+            },
+        },
+        route: || web::get().to(classification::classification_handler),
+    },
+    Endpoint {
+        path: "/convert/{ip}",
+        summary: "Decimal, hex, binary, IPv6 and reverse DNS notations of an IP address",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<ConvertResponse>(),
+            example: |req, _, format| {
+                Box::pin(async move { render_example(&format, &convert::convert(get_ip(&req))) })
+            },
+        },
+        route: || web::get().to(convert::convert_handler),
+    },
+    Endpoint {
+        path: "/subnet/{network}/{prefix}",
+        summary: "Network, broadcast, host range and size of a subnet (prefix length or netmask)",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SubnetResponse>(),
+            example: |_, _, format| {
+                Box::pin(async move {
+                    let network = "192.0.2.0/24".parse().unwrap();
+                    render_example(&format, &convert::subnet(network))
+                })
+            },
+        },
+        route: || web::get().to(convert::subnet_handler),
+    },
+    Endpoint {
+        path: "/rdap/{ip}",
+        summary: "Registration data (network name, registrant, abuse contact, dates) via RDAP",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<RdapRecord>(),
+            // Every example would be a request to the registry
+            example: |_, _, _| {
+                Box::pin(async { "(registration data of the given IP)".to_string() })
+            },
+        },
+        route: || web::get().to(rdap::rdap_handler),
+    },
+    Endpoint {
+        path: "/abuse",
+        summary: "Abuse contact of the caller IP from the local RPSL database or RDAP",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<AbuseRecord>(),
+            example: |_, _, _| {
+                Box::pin(async { "(abuse contact of the caller IP)".to_string() })
+            },
+        },
+        route: || web::get().to(abuse::abuse_handler),
+    },
+    Endpoint {
+        path: "/abuse/{ip}",
+        summary: "Abuse contact of the given IP from the local RPSL database or RDAP",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<AbuseRecord>(),
+            example: |_, _, _| {
+                Box::pin(async { "(abuse contact of the given IP)".to_string() })
+            },
+        },
+        route: || web::get().to(abuse::abuse_ip_handler),
+    },
+    Endpoint {
+        path: "/network",
+        summary: "Internal network, site and tags of the caller IP from the overlay file",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<NetworkRecord>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(&format, &network::get_network_response(&req, &state))
+                })
+            },
+        },
+        route: || web::get().to(network::network_handler),
+    },
+    Endpoint {
+        path: "/all",
+        summary: "All information about the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<AllResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    let response = all::get_all_response(&req, &state).await;
+                    render_example(&format, &response)
+                })
+            },
+        },
+        route: || web::get().to(all::all_handler),
+    },
+    Endpoint {
+        path: "/blocklist",
+        summary: "DNS blocklist status of the caller IP",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<BlocklistRecord>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    let response = blocklist::get_blocklist_response(&req, &state).await;
+                    render_example(&format, &response)
+                })
+            },
+        },
+        route: || web::get().to(blocklist::blocklist_handler),
+    },
+    Endpoint {
+        path: "/dual",
+        summary: "Starts a token that collects the caller's IPv4 and IPv6 address",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<DualStackResponse>(),
+            // Every real example would leave a token behind
+            example: |_, _, _| {
+                Box::pin(async { "(Token and the URL for the other address family)".to_string() })
+            },
+        },
+        route: || web::get().to(dual_stack::dual_handler),
+    },
+    Endpoint {
+        path: "/dual/{token}",
+        summary: "Adds the caller's address to a `/dual` token and returns both addresses",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<DualStackResponse>(),
+            example: |_, _, _| {
+                Box::pin(async { "(IPv4 and IPv6 address of the caller)".to_string() })
+            },
+        },
+        route: || web::get().to(dual_stack::dual_token_handler),
+    },
+    Endpoint {
+        path: "/headers",
+        summary: "Request headers as seen by the server, sensitive values redacted",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<HeadersResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(&format, &request_info::get_headers_response(&req, &state))
+                })
+            },
+        },
+        route: || web::get().to(request_info::headers_handler),
+    },
+    Endpoint {
+        path: "/request",
+        summary: "Method, HTTP version, headers, proxy chain and TLS state of the request",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<RequestResponse>(),
+            example: |req, state, format| {
+                Box::pin(async move {
+                    render_example(&format, &request_info::get_request_response(&req, &state))
+                })
+            },
+        },
+        route: || web::get().to(request_info::request_handler),
+    },
+    Endpoint {
+        path: "/ua",
+        summary: "Browser, operating system and device type parsed from the User-Agent header",
+        formatted: true,
+        rate_limit: RateLimitTier::Cheap,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<UserAgentRecord>(),
+            example: |req, _, format| {
+                Box::pin(async move { render_example(&format, &user_agent::get_ua_response(&req)) })
+            },
+        },
+        route: || web::get().to(user_agent::ua_handler),
+    },
+    Endpoint {
+        path: "/traceroute",
+        summary: "Traceroute back to the caller IP (or `?target=` when enabled), streamed in plain text",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[(
+            "target",
+            "Address to trace instead of the caller, requires traceroute.allow_custom_target",
+        )],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<TracerouteResponse>(),
+            // Running a real trace for every `/docs` view would be far too slow
+            example: |_, _, _| {
+                Box::pin(async { "(Hops to your IP with RTT, hostname and ASN)".to_string() })
+            },
+        },
+        route: || web::get().to(traceroute::traceroute_handler),
+    },
+    Endpoint {
+        path: "/port/{port}",
+        summary: "Whether an allowlisted TCP port on the caller IP is open, closed or filtered",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<PortCheckResponse>(),
+            // A closed or filtered port would stall `/docs` for the whole connect timeout
+            example: |_, _, _| {
+                Box::pin(async { "(open, closed or filtered for the given port)".to_string() })
+            },
+        },
+        route: || web::get().to(port_check::port_check_handler),
+    },
+    Endpoint {
+        path: "/healthz",
+        summary: "Liveness check, answers as long as the process is up",
+        formatted: true,
+        rate_limit: RateLimitTier::Exempt,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<SimpleResponse>(),
+            example: |_, _, format| {
+                Box::pin(async move { render_example(&format, &health::get_health_response()) })
+            },
+        },
+        route: || web::get().to(health::healthz_handler),
+    },
+    Endpoint {
+        path: "/readyz",
+        summary: "Readiness check of the MaxMind databases and the DNS resolver, 503 when degraded",
+        formatted: true,
+        rate_limit: RateLimitTier::Exempt,
+        query: &[],
+        body: EndpointBody::Model {
+            schema: |gen| gen.subschema_for::<ReadinessResponse>(),
+            example: |_, state, format| {
+                Box::pin(async move {
+                    let response = health::get_readiness_response(&state).await;
+                    render_example(&format, &response)
+                })
+            },
+        },
+        route: || web::get().to(health::readyz_handler),
+    },
+    Endpoint {
+        path: "/docs",
+        summary: "Documentation",
+        formatted: true,
+        rate_limit: RateLimitTier::Expensive,
+        query: &[],
+        body: EndpointBody::Document {
+            content_type: "text/plain",
+            description: "(Documentation in plain-text format)",
+        },
+        route: || web::get().to(docs::docs_handler),
+    },
+    Endpoint {
+        path: "/openapi.json",
+        summary: "OpenAPI 3 specification of this API",
+        formatted: false,
+        rate_limit: RateLimitTier::Exempt,
+        query: &[],
+        body: EndpointBody::Document {
+            content_type: "application/json",
+            description: "(OpenAPI specification in JSON format)",
+        },
+        route: || web::get().to(openapi::openapi_handler),
+    },
+];
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("")
-            .wrap(FormatMiddleware)
-            .route("/", web::get().to(root::root_handler))
-            .route("/ip", web::get().to(ip::ip_handler))
-            .route(
-                "/reverse_dns",
-                web::get().to(reverse_dns::reverse_dns_handler),
-            )
-            .route("/country", web::get().to(country::country_handler))
-            .route(
-                "/country_code",
-                web::get().to(country_code::country_code_handler),
-            )
-            .route("/city", web::get().to(city::city_handler))
-            .route("/region", web::get().to(region::region_handler))
-            .route("/asn", web::get().to(asn::asn_handler))
-            .route("/blocklist", web::get().to(blocklist::blocklist_handler))
-            .route("/all", web::get().to(all::all_handler))
-            .route("/docs", web::get().to(docs::docs_handler)),
-    );
+    // Unformatted endpoints go first, the formatted scope below matches every path
+    for endpoint in ENDPOINTS.iter().filter(|endpoint| !endpoint.formatted) {
+        cfg.route(endpoint.path, (endpoint.route)());
+    }
+
+    let scope = ENDPOINTS
+        .iter()
+        .filter(|endpoint| endpoint.formatted)
+        .fold(
+            // Middlewares run bottom to top, API keys and rate limiting need the path
+            // without extension and rate limiting needs the API key identity
+            web::scope("")
+                .wrap(RateLimitMiddleware)
+                .wrap(ApiKeyMiddleware)
+                .wrap(FormatMiddleware),
+            |scope, endpoint| scope.route(endpoint.path, (endpoint.route)()),
+        )
+        // Admin endpoints are kept out of `ENDPOINTS` so they don't show up in `/docs`
+        .route("/admin/usage", web::get().to(admin::usage_handler));
+
+    cfg.service(scope);
 }
@@ -0,0 +1,18 @@
+use crate::format_middleware::Format;
+use crate::models::NetworkRecord;
+use crate::util::{format_response, get_ip};
+use crate::AppState;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+pub async fn network_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_network_response(&req, &state),
+        false,
+    )
+}
+
+pub fn get_network_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> NetworkRecord {
+    state.network.lookup(get_ip(req))
+}
@@ -0,0 +1,124 @@
+use crate::handlers::{EndpointBody, ENDPOINTS};
+use actix_web::HttpResponse;
+use lazy_static::lazy_static;
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Map, Value};
+
+// Content types every formatted endpoint can answer with, and whether they carry the model schema
+const FORMAT_CONTENT_TYPES: &[(&str, bool)] = &[
+    ("application/json", true),
+    ("application/xml", true),
+    ("application/x-yaml", true),
+    ("application/msgpack", true),
+    ("text/csv", false),
+    ("text/plain", false),
+    ("text/event-stream", false),
+];
+
+lazy_static! {
+    static ref OPENAPI_DOCUMENT: String = build_openapi_document().to_string();
+}
+
+pub async fn openapi_handler() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(OPENAPI_DOCUMENT.as_str())
+}
+
+fn build_openapi_document() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+
+    for endpoint in ENDPOINTS {
+        let (content, description) = match &endpoint.body {
+            EndpointBody::Model { schema, .. } => {
+                let schema = serde_json::to_value(schema(&mut gen)).unwrap_or(Value::Null);
+                let content = FORMAT_CONTENT_TYPES
+                    .iter()
+                    .map(|(content_type, structured)| {
+                        let media_schema = if *structured {
+                            schema.clone()
+                        } else {
+                            json!({ "type": "string" })
+                        };
+                        (content_type.to_string(), json!({ "schema": media_schema }))
+                    })
+                    .collect::<Map<String, Value>>();
+                (
+                    content,
+                    "The format is chosen by the path extension (.json, .xml, .csv, .yaml, \
+                     .msgpack, .env, .sse, .txt) or the Accept header",
+                )
+            }
+            EndpointBody::Document { content_type, .. } => (
+                Map::from_iter([(
+                    content_type.to_string(),
+                    json!({ "schema": { "type": "string" } }),
+                )]),
+                "Successful response",
+            ),
+        };
+
+        let mut operation = json!({
+            "summary": endpoint.summary,
+            "responses": {
+                "200": {
+                    "description": description,
+                    "content": content,
+                }
+            }
+        });
+
+        let mut parameters = path_parameters(endpoint.path);
+        for (name, description) in endpoint.query {
+            parameters.push(json!({
+                "name": name,
+                "in": "query",
+                "required": false,
+                "description": description,
+                "schema": { "type": "string" }
+            }));
+        }
+        if endpoint.formatted {
+            parameters.push(json!({
+                "name": "callback",
+                "in": "query",
+                "required": false,
+                "description": "Wraps the JSON response in a JSONP callback",
+                "schema": { "type": "string" }
+            }));
+        }
+        if !parameters.is_empty() {
+            operation["parameters"] = Value::Array(parameters);
+        }
+
+        paths.insert(endpoint.path.to_string(), json!({ "get": operation }));
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "ipeek",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+        }
+    })
+}
+
+// `{name}` segments of a route pattern, which use the same syntax in actix and OpenAPI
+fn path_parameters(path: &str) -> Vec<Value> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| {
+            json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" }
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,69 @@
+use crate::config::PortCheckConfig;
+use crate::format_middleware::Format;
+use crate::models::PortCheckResponse;
+use crate::special_purpose::is_global;
+use crate::util::{format_response, get_trusted_ip};
+use crate::AppState;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+pub async fn port_check_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    port: web::Path<String>,
+) -> HttpResponse {
+    let config = &state.port_check;
+    if !config.enabled {
+        return HttpResponse::ServiceUnavailable().body("Port checks are disabled\n");
+    }
+
+    let port = match port.parse::<u16>() {
+        Ok(port) if port > 0 => port,
+        _ => return HttpResponse::BadRequest().body("Invalid port\n"),
+    };
+
+    if !config.allowed_ports.contains(&port) {
+        let allowed = config
+            .allowed_ports
+            .iter()
+            .map(|port| port.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        return HttpResponse::Forbidden().body(format!(
+            "Port {} can't be checked, allowed ports: {}\n",
+            port, allowed
+        ));
+    }
+
+    let ip = get_trusted_ip(&req, &state.trusted_proxies);
+    if !is_global(ip) {
+        return HttpResponse::Forbidden().body(format!("{} is not a public address\n", ip));
+    }
+
+    let response = check_port(ip, port, config).await;
+    format_response(req.extensions().get::<Format>().unwrap(), &response, false)
+}
+
+pub async fn check_port(ip: IpAddr, port: u16, config: &PortCheckConfig) -> PortCheckResponse {
+    let start = Instant::now();
+    let connect = TcpStream::connect(SocketAddr::new(ip, port));
+
+    let status = match timeout(Duration::from_millis(config.timeout_ms), connect).await {
+        Ok(Ok(_)) => "open",
+        Ok(Err(err)) if err.kind() == ErrorKind::ConnectionRefused => "closed",
+        // Unreachable hosts and dropped SYNs both mean something in between filters the port
+        Ok(Err(_)) | Err(_) => "filtered",
+    };
+
+    PortCheckResponse {
+        ip: ip.to_string(),
+        port,
+        status: status.to_string(),
+        duration_ms: start.elapsed().as_millis() as u64,
+    }
+}
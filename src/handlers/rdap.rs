@@ -0,0 +1,39 @@
+use crate::format_middleware::Format;
+use crate::rdap::RdapError;
+use crate::special_purpose::is_global;
+use crate::util::format_response;
+use crate::AppState;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+pub async fn rdap_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    ip: web::Path<String>,
+) -> HttpResponse {
+    if !state.rdap.enabled {
+        return HttpResponse::ServiceUnavailable().body("RDAP lookups are disabled\n");
+    }
+
+    let ip = match ip.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid IP address\n"),
+    };
+    // Registries only answer for special-purpose ranges with the IANA reservation
+    if !is_global(ip) {
+        return HttpResponse::NotFound().body("No RDAP record for special-purpose addresses\n");
+    }
+
+    let state = state.get_ref().clone();
+    match web::block(move || state.rdap.lookup(ip)).await {
+        Ok(Ok(record)) => {
+            format_response(req.extensions().get::<Format>().unwrap(), &record, false)
+        }
+        Ok(Err(RdapError::NotFound)) => HttpResponse::NotFound().body("No RDAP record found\n"),
+        Ok(Err(RdapError::Upstream(err))) => {
+            HttpResponse::BadGateway().body(format!("RDAP lookup failed: {}\n", err))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -1,4 +1,5 @@
 use crate::format_middleware::Format;
+use crate::metrics;
 use crate::models::SimpleResponse;
 use crate::util::{format_response, get_ip};
 use crate::AppState;
@@ -15,9 +16,8 @@ pub async fn region_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -
     )
 }
 
-pub fn get_region(ip: IpAddr, geo_db: &Reader<Vec<u8>>) -> Option<String> {
-    geo_db
-        .lookup::<geoip2::City>(ip)
+pub fn get_region<S: AsRef<[u8]>>(ip: IpAddr, geo_db: &Reader<S>) -> Option<String> {
+    metrics::observe_mmdb_lookup("geo", geo_db.lookup::<geoip2::City>(ip))
         .ok()?
         .subdivisions
         .as_ref()?
@@ -29,6 +29,11 @@ pub fn get_region(ip: IpAddr, geo_db: &Reader<Vec<u8>>) -> Option<String> {
         .map(String::from)
 }
 pub fn get_region_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> SimpleResponse {
-    let result = get_region(get_ip(&req), &state.geo_db).unwrap_or_else(|| "".to_string());
+    let ip = get_ip(req);
+    let result = state
+        .network
+        .region(ip)
+        .or_else(|| get_region(ip, &state.geo_db.get()))
+        .unwrap_or_default();
     SimpleResponse { value: result }
 }
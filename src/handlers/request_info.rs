@@ -0,0 +1,105 @@
+use crate::api_keys::redact_query;
+use crate::format_middleware::Format;
+use crate::models::{HeaderEntry, HeadersResponse, RequestResponse, TlsInfo};
+use crate::tls::TlsConnectionInfo;
+use crate::util::{format_response, get_ip_with_source, get_proxy_chain};
+use crate::AppState;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+const REDACTED: &str = "[redacted]";
+
+pub async fn headers_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_headers_response(&req, &state),
+        false,
+    )
+}
+
+pub async fn request_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_request_response(&req, &state),
+        false,
+    )
+}
+
+pub fn get_headers_response(
+    req: &HttpRequest,
+    state: &web::Data<Arc<AppState>>,
+) -> HeadersResponse {
+    HeadersResponse {
+        headers: get_header_entries(req, &state.headers.redact),
+    }
+}
+
+pub fn get_request_response(
+    req: &HttpRequest,
+    state: &web::Data<Arc<AppState>>,
+) -> RequestResponse {
+    let (client_ip, client_ip_source) = get_ip_with_source(req);
+    RequestResponse {
+        method: req.method().to_string(),
+        version: format!("{:?}", req.version()),
+        path: req.path().to_string(),
+        query: redact_query(req.query_string()),
+        client_ip: client_ip.to_string(),
+        client_ip_source: client_ip_source.to_string(),
+        peer_addr: req
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_default(),
+        proxy_chain: get_proxy_chain(req),
+        tls: get_tls_info(req),
+        headers: get_header_entries(req, &state.headers.redact),
+    }
+}
+
+fn get_header_entries(req: &HttpRequest, redact: &[String]) -> Vec<HeaderEntry> {
+    let mut headers: Vec<HeaderEntry> = req
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let redacted = redact
+                .iter()
+                .any(|redacted| redacted.eq_ignore_ascii_case(name.as_str()));
+
+            HeaderEntry {
+                name: name.to_string(),
+                value: if redacted {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).to_string()
+                },
+            }
+        })
+        .collect();
+
+    headers.sort_by(|a, b| a.name.cmp(&b.name));
+    headers
+}
+
+fn get_tls_info(req: &HttpRequest) -> TlsInfo {
+    if let Some(session) = req.conn_data::<TlsConnectionInfo>() {
+        return TlsInfo {
+            enabled: true,
+            terminated_by: "ipeek".to_string(),
+            version: session.version.clone(),
+            cipher_suite: session.cipher_suite.clone(),
+            alpn: session.alpn.clone(),
+            sni: session.sni.clone(),
+        };
+    }
+
+    // Reverse proxies report the original scheme via `Forwarded` / `X-Forwarded-Proto`
+    let enabled = req.connection_info().scheme() == "https";
+    TlsInfo {
+        enabled,
+        terminated_by: if enabled { "proxy" } else { "none" }.to_string(),
+        version: String::new(),
+        cipher_suite: String::new(),
+        alpn: String::new(),
+        sni: String::new(),
+    }
+}
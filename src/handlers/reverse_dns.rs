@@ -1,6 +1,7 @@
 use crate::config::DnsResolver;
 use crate::format_middleware::Format;
 use crate::models::SimpleResponse;
+use crate::special_purpose::is_global;
 use crate::util::{format_response, get_ip};
 use crate::AppState;
 use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
@@ -8,19 +9,20 @@ use std::net::IpAddr;
 use std::sync::Arc;
 
 pub async fn reverse_dns_handler(req: HttpRequest, state: web::Data<Arc<AppState>>) -> HttpResponse {
-    format_response(
-        req.extensions().get::<Format>().unwrap(),
-        &get_reverse_dns_response(&req, &state).await,
-        false,
-    )
+    let info = get_reverse_dns_response(&req, &state).await;
+    format_response(req.extensions().get::<Format>().unwrap(), &info, false)
 }
 
+// Non-global addresses have no meaningful PTR records, their lookups are skipped
 pub async fn get_reverse_dns(ip: IpAddr, resolver: &DnsResolver) -> Option<String> {
+    if !is_global(ip) {
+        return None;
+    }
     resolver.reverse_lookup(ip).await
 }
 
 pub async fn get_reverse_dns_response(req: &HttpRequest, state: &web::Data<Arc<AppState>>) -> SimpleResponse {
-    match get_reverse_dns(get_ip(&req), &state.dns_resolver).await {
+    match get_reverse_dns(get_ip(req), &state.dns_resolver).await {
         Some(result) => SimpleResponse { value: result },
         None => SimpleResponse {
             value: "".to_string(),
@@ -0,0 +1,220 @@
+use crate::config::TracerouteConfig;
+use crate::format_middleware::Format;
+use crate::handlers::asn::lookup_asn;
+use crate::handlers::country_code::get_country_code;
+use crate::handlers::reverse_dns::get_reverse_dns;
+use crate::models::{ToPlainText, TracerouteHop, TracerouteResponse};
+use crate::special_purpose::is_global;
+use crate::streaming;
+use crate::util::{format_response, get_trusted_ip};
+use crate::AppState;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+use tracert::node::Node;
+use tracert::trace::{TraceResult, TraceStatus, Tracer};
+
+// Traces share one raw ICMP socket view, concurrent traces would see each other's replies
+static TRACE_PERMITS: Semaphore = Semaphore::const_new(1);
+
+#[derive(Deserialize)]
+pub struct TracerouteQuery {
+    target: Option<String>,
+}
+
+enum TraceEvent {
+    Hop(Node),
+    Done(Result<TraceResult, String>),
+}
+
+pub async fn traceroute_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TracerouteQuery>,
+) -> HttpResponse {
+    let config = &state.traceroute;
+    if !config.enabled {
+        return HttpResponse::ServiceUnavailable().body("Traceroute is disabled\n");
+    }
+
+    let target = match &query.target {
+        Some(_) if !config.allow_custom_target => {
+            return HttpResponse::Forbidden().body("Custom traceroute targets are disabled\n");
+        }
+        Some(target) => match target.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return HttpResponse::BadRequest().body("Invalid traceroute target\n"),
+        },
+        None => get_trusted_ip(&req, &state.trusted_proxies),
+    };
+    if !is_global(target) {
+        return HttpResponse::Forbidden().body(format!("{} is not a public address\n", target));
+    }
+
+    let permit = match TRACE_PERMITS.try_acquire() {
+        Ok(permit) => permit,
+        Err(_) => {
+            return HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", "5"))
+                .body("Another traceroute is in progress\n");
+        }
+    };
+
+    let mut events = match start_trace(target, config) {
+        Ok(events) => events,
+        Err(err) => {
+            return HttpResponse::ServiceUnavailable().body(format!("Traceroute failed: {}\n", err))
+        }
+    };
+
+    let format = req.extensions().get::<Format>().unwrap().clone();
+    if format != Format::Plain && format != Format::Txt {
+        let response = collect_trace(target, &mut events, &state).await;
+        drop(permit);
+        return format_response(&format, &response, false);
+    }
+
+    // Plain text is streamed hop by hop while the trace is running
+    let (tx, body) = streaming::channel();
+    let max_hops = config.max_hops;
+    actix_web::rt::spawn(async move {
+        let _permit = permit;
+        let _ = tx.send(Bytes::from(format!(
+            "{}\n",
+            TracerouteResponse::header(&target.to_string(), max_hops)
+        )));
+
+        while let Some(event) = events.recv().await {
+            let line = match event {
+                TraceEvent::Hop(node) => annotate_hop(&node, &state).await.to_plain_text(),
+                TraceEvent::Done(result) => finish(target, max_hops, Vec::new(), result).summary(),
+            };
+            if tx.send(Bytes::from(format!("{}\n", line))).is_err() {
+                break;
+            }
+        }
+    });
+
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+async fn collect_trace(
+    target: IpAddr,
+    events: &mut mpsc::UnboundedReceiver<TraceEvent>,
+    state: &web::Data<Arc<AppState>>,
+) -> TracerouteResponse {
+    let mut hops = Vec::new();
+
+    while let Some(event) = events.recv().await {
+        match event {
+            TraceEvent::Hop(node) => hops.push(annotate_hop(&node, state).await),
+            TraceEvent::Done(result) => {
+                return finish(target, state.traceroute.max_hops, hops, result)
+            }
+        }
+    }
+
+    finish(
+        target,
+        state.traceroute.max_hops,
+        hops,
+        Err("trace ended unexpectedly".to_string()),
+    )
+}
+
+fn finish(
+    target: IpAddr,
+    max_hops: u8,
+    hops: Vec<TracerouteHop>,
+    result: Result<TraceResult, String>,
+) -> TracerouteResponse {
+    let (status, duration, error) = match result {
+        Ok(result) => {
+            let status = match result.status {
+                TraceStatus::Done => "done",
+                TraceStatus::Error => "error",
+                TraceStatus::Timeout => "timeout",
+            };
+            (status, result.probe_time, String::new())
+        }
+        Err(err) => ("error", Duration::ZERO, err),
+    };
+
+    TracerouteResponse {
+        target: target.to_string(),
+        max_hops,
+        status: status.to_string(),
+        duration_ms: duration.as_millis() as u64,
+        hops,
+        error,
+    }
+}
+
+async fn annotate_hop(node: &Node, state: &web::Data<Arc<AppState>>) -> TracerouteHop {
+    let asn = state
+        .network
+        .asn(node.ip_addr)
+        .unwrap_or_else(|| lookup_asn(node.ip_addr, &state.asn_db.get()));
+
+    TracerouteHop {
+        hop: node.hop.unwrap_or(node.seq),
+        ip: node.ip_addr.to_string(),
+        hostname: get_reverse_dns(node.ip_addr, &state.dns_resolver)
+            .await
+            .unwrap_or_default(),
+        rtt_ms: node.rtt.as_secs_f64() * 1000.0,
+        asn: asn.asn.unwrap_or(0),
+        aso: asn.aso.unwrap_or_default(),
+        country_code: state
+            .network
+            .country_code(node.ip_addr)
+            .or_else(|| get_country_code(node.ip_addr, &state.geo_db.get()))
+            .unwrap_or_default(),
+    }
+}
+
+// Runs the blocking tracer on its own thread and forwards its progress
+fn start_trace(
+    target: IpAddr,
+    config: &TracerouteConfig,
+) -> Result<mpsc::UnboundedReceiver<TraceEvent>, String> {
+    let mut tracer = Tracer::new(target)?;
+    tracer.set_max_hop(config.max_hops);
+    tracer.set_trace_timeout(Duration::from_millis(config.timeout_ms));
+    tracer.set_receive_timeout(Duration::from_millis(config.receive_timeout_ms));
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let progress = tracer.get_progress_receiver();
+    let progress_tx = tx.clone();
+
+    std::thread::spawn(move || {
+        let forwarder = std::thread::spawn(move || {
+            while let Ok(node) = progress.lock().unwrap().recv() {
+                if progress_tx.send(TraceEvent::Hop(node)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let start = Instant::now();
+        // `tracert` panics instead of erroring when it can't open raw sockets
+        let result = catch_unwind(AssertUnwindSafe(|| tracer.trace())).unwrap_or_else(|_| {
+            Err(format!(
+                "could not open raw sockets after {} ms, missing CAP_NET_RAW?",
+                start.elapsed().as_millis()
+            ))
+        });
+
+        // Dropping the tracer closes the progress channel and ends the forwarder
+        drop(tracer);
+        let _ = forwarder.join();
+        let _ = tx.send(TraceEvent::Done(result));
+    });
+
+    Ok(rx)
+}
@@ -0,0 +1,17 @@
+use crate::format_middleware::Format;
+use crate::models::UserAgentRecord;
+use crate::user_agent::request_user_agent;
+use crate::util::format_response;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+
+pub async fn ua_handler(req: HttpRequest) -> HttpResponse {
+    format_response(
+        req.extensions().get::<Format>().unwrap(),
+        &get_ua_response(&req),
+        false,
+    )
+}
+
+pub fn get_ua_response(req: &HttpRequest) -> UserAgentRecord {
+    request_user_agent(req)
+}
@@ -0,0 +1,113 @@
+use crate::config::ListenerConfig;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+
+const UNIX_PREFIX: &str = "unix:";
+
+pub enum BoundListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+impl BoundListener {
+    // Human readable address including the scheme, for the startup log
+    pub fn describe(&self, tls: bool) -> String {
+        match self {
+            BoundListener::Tcp(listener) => {
+                let scheme = if tls { "https" } else { "http" };
+                match listener.local_addr() {
+                    Ok(address) => format!("{}://{}", scheme, address),
+                    Err(_) => format!("{}://(unknown)", scheme),
+                }
+            }
+            #[cfg(unix)]
+            BoundListener::Unix(listener) => listener
+                .local_addr()
+                .ok()
+                .and_then(|address| address.as_pathname().map(|path| path.display().to_string()))
+                .map(|path| format!("unix:{}", path))
+                .unwrap_or_else(|| "unix:(unnamed)".to_string()),
+        }
+    }
+}
+
+// Binds every address a listener resolves to, e.g. both `127.0.0.1` and `::1` for `localhost`
+pub fn bind(config: &ListenerConfig) -> io::Result<Vec<BoundListener>> {
+    if let Some(path) = config.address.strip_prefix(UNIX_PREFIX) {
+        if config.tls {
+            return Err(invalid_input(format!(
+                "{}: TLS is not supported on Unix sockets",
+                config.address
+            )));
+        }
+        return bind_unix(path).map(|listener| vec![listener]);
+    }
+
+    let addresses: Vec<SocketAddr> = config.address.to_socket_addrs()?.collect();
+    if addresses.is_empty() {
+        return Err(invalid_input(format!(
+            "{}: address did not resolve",
+            config.address
+        )));
+    }
+
+    addresses
+        .into_iter()
+        .map(|address| bind_tcp(address, config).map(BoundListener::Tcp))
+        .collect()
+}
+
+fn bind_tcp(address: SocketAddr, config: &ListenerConfig) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    socket.set_reuse_address(true)?;
+
+    match (address, config.v6_only) {
+        (SocketAddr::V6(_), Some(v6_only)) => socket.set_only_v6(v6_only)?,
+        (SocketAddr::V4(_), Some(_)) => {
+            return Err(invalid_input(format!(
+                "{}: v6_only only applies to IPv6 addresses",
+                config.address
+            )))
+        }
+        _ => {}
+    }
+
+    socket.bind(&address.into())?;
+    socket.listen(config.backlog as i32)?;
+    Ok(socket.into())
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &str) -> io::Result<BoundListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // A socket left behind by a previous run would make the bind fail
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    std::os::unix::net::UnixListener::bind(path).map(BoundListener::Unix)
+}
+
+#[cfg(not(unix))]
+fn bind_unix(path: &str) -> io::Result<BoundListener> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{}{}: Unix sockets are not supported on this platform",
+            UNIX_PREFIX, path
+        ),
+    ))
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -1,50 +1,210 @@
+mod abuse;
+mod address_family;
+mod api_keys;
+mod cli;
 mod config;
+mod feeds;
 mod format_middleware;
 mod handlers;
+mod listener;
+mod metrics;
+mod mmdb;
 mod models;
+mod network_overlay;
+mod prefix_tree;
+mod rate_limit;
+mod rdap;
+mod special_purpose;
+mod streaming;
+mod tls;
+mod updater;
+mod user_agent;
 mod util;
 use actix_web::middleware::Logger;
 use actix_web::{web, App, HttpServer};
+use clap::Parser;
 use env_logger::Env;
-use maxminddb::Reader;
 use std::sync::Arc;
 use std::time::Duration;
 
-struct AppState {
-    geo_db: Reader<Vec<u8>>,
-    asn_db: Reader<Vec<u8>>,
+struct AppState<S: AsRef<[u8]> = mmdb::Storage> {
+    geo_db: mmdb::SharedReader<S>,
+    asn_db: mmdb::SharedReader<S>,
     dns_resolver: config::DnsResolver,
+    health: config::HealthConfig,
+    rate_limiter: rate_limit::RateLimiter,
+    api_keys: api_keys::ApiKeyStore,
+    traceroute: config::TracerouteConfig,
+    headers: config::HeadersConfig,
+    port_check: config::PortCheckConfig,
+    trusted_proxies: prefix_tree::PrefixTree<()>,
+    address_families: address_family::AddressFamilies,
+    network: network_overlay::NetworkOverlay,
+    rdap: rdap::RdapClient,
+    abuse: abuse::AbuseContacts,
+    feeds: Arc<feeds::Feeds>,
+    blocklist: config::BlocklistConfig,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let config = config::load_config("config.toml").expect("Failed to load configuration");
+    let cli = cli::Cli::parse();
+    let config = config::load_config(&cli).unwrap_or_else(|err| exit_with_config_error(err));
+    let updater = updater::Updater::new(&config.updater, &config.geo_db_path, &config.asn_db_path);
 
-    let geo_reader =
-        Reader::open_readfile(&config.geo_db_path).expect("Could not open GeoLite2 City database");
-    let asn_reader =
-        Reader::open_readfile(&config.asn_db_path).expect("Could not open GeoLite2 ASN database");
-    
+    if let Some(cli::Command::UpdateDb) = cli.command {
+        let mut failed = false;
+        for (target, result) in updater.run() {
+            match result {
+                Ok(updater::UpdateOutcome::Updated) => {
+                    println!("{}: downloaded {}", target.edition, target.path)
+                }
+                Ok(updater::UpdateOutcome::UpToDate) => println!("{}: up to date", target.edition),
+                Err(err) => {
+                    eprintln!("{}: {}", target.edition, err);
+                    failed = true;
+                }
+            }
+        }
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+
+    if let Err(err) = config.validate_databases() {
+        exit_with_config_error(err);
+    }
+
+    let geo_reader = mmdb::SharedReader::open("geo", &config.geo_db_path, config.mmap_databases)
+        .unwrap_or_else(|err| exit_with_config_error(config::invalid("geo_db_path", err)));
+    let asn_reader = mmdb::SharedReader::open("asn", &config.asn_db_path, config.mmap_databases)
+        .unwrap_or_else(|err| exit_with_config_error(config::invalid("asn_db_path", err)));
+
+    let listeners = config.listeners();
     let dns_resolver = config::DnsResolver::new(&config.dns);
+    let api_keys = api_keys::ApiKeyStore::load(&config.api_keys)
+        .unwrap_or_else(|err| exit_with_config_error(config::invalid("api_keys.key_file", err)));
+    let network = network_overlay::NetworkOverlay::load(&config.network).unwrap_or_else(|err| {
+        exit_with_config_error(config::invalid("network.overlay_path", err))
+    });
+    if let Some(overlay_path) = &config.network.overlay_path {
+        println!("Loaded {} networks from {}", network.len(), overlay_path);
+    }
+    let rdap = rdap::RdapClient::new(&config.rdap)
+        .unwrap_or_else(|err| exit_with_config_error(config::invalid("rdap.bootstrap_files", err)));
+    let abuse = abuse::AbuseContacts::new(&config.abuse)
+        .unwrap_or_else(|err| exit_with_config_error(config::invalid("abuse.database_path", err)));
+    if let Some(database_path) = &config.abuse.database_path {
+        println!("Loaded abuse contacts of {} networks from {}", abuse.len(), database_path);
+    }
+    let feeds = feeds::Feeds::load(&config.blocklist.feeds)
+        .map(Arc::new)
+        .unwrap_or_else(|err| exit_with_config_error(config::invalid("blocklist.feeds", err)));
+    for (name, size) in feeds.sizes() {
+        println!("Loaded {} networks of feed {}", size, name);
+    }
+    let trusted_proxies = config.trusted_proxies();
+    let log_filter = config.log_level.clone();
+    let updater_enabled = config.updater.enabled;
+    let feed_reload_interval = (!config.blocklist.feeds.is_empty())
+        .then(|| Duration::from_secs(config.blocklist.feed_reload_interval_secs));
 
     let shared_state = Arc::new(AppState {
         geo_db: geo_reader,
         asn_db: asn_reader,
         dns_resolver,
+        health: config.health,
+        rate_limiter: rate_limit::RateLimiter::new(&config.rate_limit),
+        api_keys,
+        traceroute: config.traceroute,
+        headers: config.headers,
+        port_check: config.port_check,
+        trusted_proxies,
+        address_families: address_family::AddressFamilies::new(&config.address_family),
+        network,
+        rdap,
+        abuse,
+        feeds: feeds.clone(),
+        blocklist: config.blocklist,
     });
 
-    println!("Starting ipeek on http://{}", config.server_address);
+    let mut logger = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+    if let Some(filter) = &log_filter {
+        logger.parse_filters(filter);
+    }
+    logger.init();
+
+    if updater_enabled {
+        updater::spawn(updater, shared_state.clone());
+    }
+    address_family::spawn_token_prune(shared_state.clone());
+    if shared_state.rdap.enabled {
+        rdap::spawn_cache_prune(shared_state.rdap.cache());
+    }
+    if let Some(interval) = feed_reload_interval {
+        feeds::spawn_reload(feeds, interval);
+    }
 
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    // `/metrics` is either served on its own listener or next to the API
+    let metrics_inline = config.metrics.enabled && config.metrics.server_address.is_none();
+    if let (true, Some(metrics_address)) =
+        (config.metrics.enabled, &config.metrics.server_address)
+    {
+        println!("Serving metrics on http://{}/metrics", metrics_address);
+        let metrics_server = HttpServer::new(|| {
+            App::new().route("/metrics", web::get().to(handlers::metrics::metrics_handler))
+        })
+        .workers(1)
+        .bind(metrics_address)?
+        .run();
+        actix_web::rt::spawn(metrics_server);
+    }
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(shared_state.clone()))
-            .wrap(Logger::new("%a %r %s %D"))
-            .configure(handlers::init_routes)
+            .wrap(address_family::AddressFamilyMiddleware)
+            .wrap(
+                Logger::new("%a %{request}xi %s %D")
+                    .custom_request_replace("request", api_keys::redacted_request_line),
+            )
+            .wrap(metrics::MetricsMiddleware)
+            .configure(|cfg| {
+                if metrics_inline {
+                    cfg.route("/metrics", web::get().to(handlers::metrics::metrics_handler));
+                }
+                handlers::init_routes(cfg);
+            })
     })
-    .client_request_timeout(Duration::from_secs(30))
-    .bind(&config.server_address)?
-    .run()
-    .await
+    .on_connect(tls::on_connect)
+    .client_request_timeout(Duration::from_secs(30));
+
+    // The certificate is only loaded when at least one listener serves HTTPS
+    let tls_config = if listeners.iter().any(|listener| listener.tls) {
+        let (tls_config, resolver) = tls::server_config(&config.tls)
+            .unwrap_or_else(|err| exit_with_config_error(config::invalid("tls.cert_path", err)));
+        tls::spawn_reload(resolver, Duration::from_secs(config.tls.reload_interval_secs));
+        Some(tls_config)
+    } else {
+        None
+    };
+
+    for listener_config in &listeners {
+        for bound in listener::bind(listener_config)? {
+            println!("Starting ipeek on {}", bound.describe(listener_config.tls));
+            server = match (bound, &tls_config) {
+                (listener::BoundListener::Tcp(lst), Some(tls_config)) if listener_config.tls => {
+                    server.listen_rustls_0_23(lst, tls_config.clone())?
+                }
+                (listener::BoundListener::Tcp(lst), _) => server.listen(lst)?,
+                #[cfg(unix)]
+                (listener::BoundListener::Unix(lst), _) => server.listen_uds(lst)?,
+            };
+        }
+    }
+
+    server.run().await
+}
+
+fn exit_with_config_error(err: ::config::ConfigError) -> ! {
+    eprintln!("ipeek: {}", err);
+    std::process::exit(2)
 }
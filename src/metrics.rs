@@ -0,0 +1,155 @@
+use crate::format_middleware::Format;
+use crate::util::matched_pattern;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpMessage};
+use lazy_static::lazy_static;
+use maxminddb::MaxMindDBError;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "ipeek_http_requests_total",
+        "Number of HTTP requests by route, format and status",
+        &["route", "format", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "ipeek_http_request_duration_seconds",
+        "HTTP request latency by route and format",
+        &["route", "format"]
+    )
+    .unwrap();
+    static ref DNS_LOOKUP_DURATION: HistogramVec = register_histogram_vec!(
+        "ipeek_dns_lookup_duration_seconds",
+        "DNS lookup latency by provider",
+        &["provider"]
+    )
+    .unwrap();
+    static ref DNS_LOOKUP_TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "ipeek_dns_lookup_timeouts_total",
+        "Number of DNS lookups that ran into the configured timeout by provider",
+        &["provider"]
+    )
+    .unwrap();
+    static ref MMDB_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "ipeek_mmdb_lookups_total",
+        "Number of MaxMind database lookups by database and result",
+        &["database", "result"]
+    )
+    .unwrap();
+    static ref MMDB_BUILD_EPOCH: IntGaugeVec = register_int_gauge_vec!(
+        "ipeek_mmdb_build_epoch_seconds",
+        "Build time of the loaded MaxMind databases as a unix timestamp",
+        &["database"]
+    )
+    .unwrap();
+}
+
+pub fn observe_dns_lookup(provider: &str, elapsed: Duration, timed_out: bool) {
+    DNS_LOOKUP_DURATION
+        .with_label_values(&[provider])
+        .observe(elapsed.as_secs_f64());
+
+    if timed_out {
+        DNS_LOOKUP_TIMEOUTS.with_label_values(&[provider]).inc();
+    }
+}
+
+// Counts a MaxMind lookup as hit or miss and passes the result through
+pub fn observe_mmdb_lookup<T>(
+    database: &str,
+    result: Result<T, MaxMindDBError>,
+) -> Result<T, MaxMindDBError> {
+    let outcome = if result.is_ok() { "hit" } else { "miss" };
+    MMDB_LOOKUPS.with_label_values(&[database, outcome]).inc();
+    result
+}
+
+pub fn set_mmdb_build_epoch(database: &str, build_epoch: u64) {
+    MMDB_BUILD_EPOCH
+        .with_label_values(&[database])
+        .set(build_epoch as i64);
+}
+
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        return format!("# failed to encode metrics: {}\n", err);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+// Middleware recording request counts and latencies per matched route and format
+pub struct MetricsMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for MetricsMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = MetricsMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MetricsMiddlewareService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct MetricsMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for MetricsMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let start = Instant::now();
+
+        Box::pin(async move {
+            let res = service.call(req).await?;
+
+            // Unmatched paths share one label so scanners can't blow up the cardinality
+            let route = matched_pattern(res.request()).unwrap_or_else(|| "unmatched".to_string());
+            let format = res
+                .request()
+                .extensions()
+                .get::<Format>()
+                .map(|format| format.to_string())
+                .filter(|format| !format.is_empty())
+                .unwrap_or_else(|| "plain".to_string());
+
+            HTTP_REQUESTS
+                .with_label_values(&[&route, &format, res.status().as_str()])
+                .inc();
+            HTTP_REQUEST_DURATION
+                .with_label_values(&[&route, &format])
+                .observe(start.elapsed().as_secs_f64());
+
+            Ok(res)
+        })
+    }
+}
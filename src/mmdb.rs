@@ -0,0 +1,98 @@
+use crate::metrics;
+use maxminddb::{MaxMindDBError, Reader};
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+// Backing storage of the configured readers, either the whole file on the heap or a read-only
+// mapping whose pages are shared with every other process mapping the same file
+pub enum Storage {
+    Heap(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl AsRef<[u8]> for Storage {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Storage::Heap(bytes) => bytes,
+            Storage::Mapped(map) => map,
+        }
+    }
+}
+
+impl Storage {
+    pub fn open(path: &str, mmap: bool) -> Result<Reader<Storage>, MaxMindDBError> {
+        let storage = if mmap {
+            // Safety: the mapping is read-only and database files are only ever replaced by a
+            // rename, which leaves the mapped inode untouched
+            Storage::Mapped(unsafe { Mmap::map(&File::open(path)?)? })
+        } else {
+            Storage::Heap(fs::read(path)?)
+        };
+        Reader::from_source(storage)
+    }
+}
+
+type Loader<S> = Box<dyn Fn(&str) -> Result<Reader<S>, MaxMindDBError> + Send + Sync>;
+
+// MaxMind reader that can be replaced at runtime, e.g. after a database update. Lookups hold
+// their own `Arc`, so a swap never invalidates a running request
+pub struct SharedReader<S: AsRef<[u8]> = Storage> {
+    // Metrics label, `geo` or `asn`
+    pub name: &'static str,
+    pub path: String,
+    reader: RwLock<Arc<Reader<S>>>,
+    modified: Mutex<Option<SystemTime>>,
+    loader: Loader<S>,
+}
+
+impl SharedReader<Storage> {
+    pub fn open(name: &'static str, path: &str, mmap: bool) -> Result<Self, MaxMindDBError> {
+        Self::with_loader(name, path, move |path| Storage::open(path, mmap))
+    }
+}
+
+impl<S: AsRef<[u8]>> SharedReader<S> {
+    pub fn with_loader(
+        name: &'static str,
+        path: &str,
+        loader: impl Fn(&str) -> Result<Reader<S>, MaxMindDBError> + Send + Sync + 'static,
+    ) -> Result<Self, MaxMindDBError> {
+        let modified = modified(path);
+        let reader = loader(path)?;
+        metrics::set_mmdb_build_epoch(name, reader.metadata.build_epoch);
+
+        Ok(Self {
+            name,
+            path: path.to_string(),
+            reader: RwLock::new(Arc::new(reader)),
+            modified: Mutex::new(modified),
+            loader: Box::new(loader),
+        })
+    }
+
+    pub fn get(&self) -> Arc<Reader<S>> {
+        self.reader.read().unwrap().clone()
+    }
+
+    // Re-opens the file when it changed on disk, a broken file keeps the current reader in place.
+    // Mapped files have to be replaced by a rename, as the updater does, never rewritten in place
+    pub fn reload_if_changed(&self) -> Result<bool, MaxMindDBError> {
+        let current = modified(&self.path);
+        let mut last_modified = self.modified.lock().unwrap();
+        if current.is_none() || current == *last_modified {
+            return Ok(false);
+        }
+
+        let reader = (self.loader)(&self.path)?;
+        metrics::set_mmdb_build_epoch(self.name, reader.metadata.build_epoch);
+        *self.reader.write().unwrap() = Arc::new(reader);
+        *last_modified = current;
+        Ok(true)
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
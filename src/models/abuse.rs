@@ -0,0 +1,63 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Debug, Serialize, Clone, Default, YaSerialize, JsonSchema)]
+pub struct AbuseContact {
+    pub email: String,
+    // Role or organization the mailbox belongs to
+    pub name: String,
+    // Network the contact is registered for, as published by the source
+    pub network: String,
+    // Local database file or RDAP URL the contact was taken from
+    pub source: String,
+}
+
+#[derive(Debug, Serialize, Clone, Default, YaSerialize, JsonSchema)]
+pub struct AbuseRecord {
+    pub ip: String,
+    pub abuse_contact: AbuseContact,
+}
+
+#[derive(Serialize)]
+pub struct CsvAbuseEntry {
+    pub ip: String,
+    pub email: String,
+    pub name: String,
+    pub network: String,
+    pub source: String,
+}
+
+impl ToPlainText for AbuseContact {
+    fn to_plain_text(&self) -> String {
+        if self.name.is_empty() {
+            self.email.clone()
+        } else {
+            format!("{} ({})", self.email, self.name)
+        }
+    }
+}
+
+impl ToPlainText for AbuseRecord {
+    fn to_plain_text(&self) -> String {
+        format!(
+            "Abuse contact: {}\nNetwork: {}\nSource: {}",
+            self.abuse_contact.to_plain_text(),
+            self.abuse_contact.network,
+            self.abuse_contact.source,
+        )
+    }
+}
+
+impl ToCsv<CsvAbuseEntry> for AbuseRecord {
+    fn to_csv_entries(&self) -> Vec<CsvAbuseEntry> {
+        vec![CsvAbuseEntry {
+            ip: self.ip.clone(),
+            email: self.abuse_contact.email.clone(),
+            name: self.abuse_contact.name.clone(),
+            network: self.abuse_contact.network.clone(),
+            source: self.abuse_contact.source.clone(),
+        }]
+    }
+}
@@ -1,23 +1,32 @@
+use schemars::JsonSchema;
 use serde::Serialize;
 use yaserde_derive::YaSerialize;
 use crate::models::traits::{ToCsv, ToPlainText};
-use crate::models::{AsnRecord, BlocklistRecord};
+use crate::models::{
+    AddressClassification, AsnRecord, BlocklistRecord, NetworkRecord, UserAgentRecord,
+};
 
-#[derive(Serialize, YaSerialize)]
+#[derive(Serialize, YaSerialize, JsonSchema)]
 pub struct AllResponse {
     pub ip: String,
+    pub classification: AddressClassification,
     pub reverse_dns: String,
     pub country: String,
     pub country_code: String,
     pub region: String,
     pub city: String,
     pub asn: AsnRecord,
+    // Internal network from the overlay file
+    pub network: NetworkRecord,
     pub blocklist: BlocklistRecord,
+    pub user_agent: UserAgentRecord,
 }
 
 #[derive(Serialize)]
 pub struct CsvInfoEntry {
     pub ip: String,
+    pub range: String,
+    pub global: bool,
     pub reverse_dns: String,
     pub country: String,
     pub country_code: String,
@@ -25,22 +34,35 @@ pub struct CsvInfoEntry {
     pub city: String,
     pub asn: u32,
     pub aso: String,
+    pub network: String,
+    pub site: String,
     pub blocked: bool,
+    pub blocklist_score: u32,
+    pub blocklist_verdict: String,
     pub blocklist_listed_in: String,
+    pub domain_blocklist_listed_in: String,
+    pub feed_listed_in: String,
+    pub abuse_contact: String,
+    pub browser: String,
+    pub os: String,
+    pub device: String,
 }
 
 impl ToPlainText for AllResponse {
     fn to_plain_text(&self) -> String {
         format!(
-            "IP: {}\nHostname: {}\nCountry: {} ({})\nRegion: {}\nCity: {}\nASN: {}\nBlocklist: {}",
+            "IP: {}\n{}\nHostname: {}\nCountry: {} ({})\nRegion: {}\nCity: {}\nASN: {}\n{}\nBlocklist: {}\nUser agent: {}",
             self.ip,
+            self.classification.to_plain_text(),
             self.reverse_dns,
             self.country,
             self.country_code,
             self.region,
             self.city,
             self.asn.to_plain_text(),
+            self.network.to_plain_text(),
             self.blocklist.to_plain_text(),
+            self.user_agent.to_plain_text(),
         )
     }
 }
@@ -49,6 +71,8 @@ impl ToCsv<CsvInfoEntry> for AllResponse {
     fn to_csv_entries(&self) -> Vec<CsvInfoEntry> {
         vec![CsvInfoEntry {
             ip: self.ip.clone(),
+            range: self.classification.range.clone(),
+            global: self.classification.global,
             reverse_dns: self.reverse_dns.clone(),
             country: self.country.clone(),
             country_code: self.country_code.clone(),
@@ -56,7 +80,11 @@ impl ToCsv<CsvInfoEntry> for AllResponse {
             city: self.city.clone(),
             aso: self.asn.aso.clone().unwrap_or("".to_string()),
             asn: self.asn.asn.unwrap_or(0),
+            network: self.network.network.clone(),
+            site: self.network.site.clone(),
             blocked: self.blocklist.blocked,
+            blocklist_score: self.blocklist.score,
+            blocklist_verdict: self.blocklist.verdict.clone(),
             blocklist_listed_in: self
                 .blocklist
                 .listed_in
@@ -65,6 +93,33 @@ impl ToCsv<CsvInfoEntry> for AllResponse {
                 .map(|entry| format!("{} ({:?})", entry.dnsbl, entry.reason))
                 .collect::<Vec<String>>()
                 .join(":"),
+            domain_blocklist_listed_in: self
+                .blocklist
+                .domain_listed_in
+                .iter()
+                .map(|entry| format!("{} ({:?})", entry.dnsbl, entry.reason))
+                .collect::<Vec<String>>()
+                .join(":"),
+            feed_listed_in: self
+                .blocklist
+                .feed_listed_in
+                .iter()
+                .map(|listing| format!("{} ({})", listing.feed, listing.category))
+                .collect::<Vec<String>>()
+                .join(":"),
+            abuse_contact: self
+                .blocklist
+                .abuse_contact
+                .as_ref()
+                .map(|contact| contact.email.clone())
+                .unwrap_or_default(),
+            browser: format!("{} {}", self.user_agent.browser, self.user_agent.browser_version)
+                .trim()
+                .to_string(),
+            os: format!("{} {}", self.user_agent.os, self.user_agent.os_version)
+                .trim()
+                .to_string(),
+            device: self.user_agent.device.clone(),
         }]
     }
 } 
\ No newline at end of file
@@ -0,0 +1,73 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Serialize, YaSerialize, JsonSchema)]
+pub struct ApiKeyUsageResponse {
+    pub keys: Vec<ApiKeyUsage>,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct ApiKeyUsage {
+    pub name: String,
+    pub admin: bool,
+    pub requests: u64,
+    pub rejected: u64,
+    pub last_used: u64,
+    pub endpoints: Vec<EndpointUsage>,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct EndpointUsage {
+    pub endpoint: String,
+    pub requests: u64,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyUsageCsvEntry {
+    pub name: String,
+    pub endpoint: String,
+    pub requests: u64,
+}
+
+impl ToPlainText for ApiKeyUsageResponse {
+    fn to_plain_text(&self) -> String {
+        self.keys
+            .iter()
+            .map(|key| {
+                let mut result = format!(
+                    "Key: {}{}\nRequests: {}\nRejected: {}\nLast used: {}",
+                    key.name,
+                    if key.admin { " (admin)" } else { "" },
+                    key.requests,
+                    key.rejected,
+                    key.last_used
+                );
+                for endpoint in &key.endpoints {
+                    result.push_str(&format!(
+                        "\n - {} ({})",
+                        endpoint.endpoint, endpoint.requests
+                    ));
+                }
+                result
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+}
+
+impl ToCsv<ApiKeyUsageCsvEntry> for ApiKeyUsageResponse {
+    fn to_csv_entries(&self) -> Vec<ApiKeyUsageCsvEntry> {
+        self.keys
+            .iter()
+            .flat_map(|key| {
+                key.endpoints.iter().map(|endpoint| ApiKeyUsageCsvEntry {
+                    name: key.name.clone(),
+                    endpoint: endpoint.endpoint.clone(),
+                    requests: endpoint.requests,
+                })
+            })
+            .collect()
+    }
+}
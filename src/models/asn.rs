@@ -1,13 +1,16 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use yaserde_derive::YaSerialize;
 use crate::models::traits::{ToCsv, ToPlainText};
 
-#[derive(Debug, Deserialize, Serialize, Clone, YaSerialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, YaSerialize, JsonSchema)]
 pub struct AsnRecord {
     #[serde(rename(deserialize = "autonomous_system_organization"))]
+    #[schemars(rename = "aso")]
     pub aso: Option<String>,
 
     #[serde(rename(deserialize = "autonomous_system_number"))]
+    #[schemars(rename = "asn")]
     pub asn: Option<u32>,
 }
 
@@ -15,7 +18,7 @@ impl ToPlainText for AsnRecord {
     fn to_plain_text(&self) -> String {
         format!(
             "ASN: {}\nOrganization: {}",
-            self.asn.clone().unwrap_or_else(|| 0),
+            self.asn.unwrap_or(0),
             self.aso.clone().unwrap_or("".to_string())
         )
     }
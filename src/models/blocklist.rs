@@ -1,42 +1,78 @@
+use schemars::JsonSchema;
 use serde::Serialize;
 use yaserde_derive::YaSerialize;
 use crate::models::traits::{ToCsv, ToPlainText};
+use crate::models::AbuseContact;
 
-#[derive(Serialize, Debug, Clone, PartialEq, YaSerialize)]
+#[derive(Serialize, Debug, Clone, PartialEq, YaSerialize, JsonSchema)]
 pub enum BlocklistReason {
     SpamSource,
     SpamSupport,
     ExploitedOrMalicious,
     DynamicResidential,
+    Phishing,
+    Malware,
     Unknown,
 }
 
-#[derive(Serialize, YaSerialize)]
+#[derive(Serialize, YaSerialize, JsonSchema)]
 pub struct BlocklistRecord {
     pub ip: String,
+    // Listed in an IP or a domain blocklist
     pub blocked: bool,
+    // Weighted severity of all listings, 0-100
+    pub score: u32,
+    // clean, suspicious or bad, depending on the score
+    pub verdict: String,
     pub listed_in: Vec<BlocklistEntry>,
+    // PTR name of the IP and the registered domain checked against the domain blocklists
+    pub hostname: String,
+    pub domain: String,
+    pub domain_listed_in: Vec<BlocklistEntry>,
+    // Local feed files containing the IP
+    pub feed_listed_in: Vec<FeedListing>,
+    // Who to report the address to, only looked up when it is listed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abuse_contact: Option<AbuseContact>,
 }
 
-#[derive(Serialize, YaSerialize, Clone)]
+#[derive(Serialize, YaSerialize, Clone, JsonSchema)]
 pub struct BlocklistEntry {
     pub dnsbl: String,
     pub reason: BlocklistReason,
 }
 
+#[derive(Serialize, YaSerialize, Clone, JsonSchema)]
+pub struct FeedListing {
+    pub feed: String,
+    pub category: String,
+    // Most specific network of the feed containing the IP
+    pub network: String,
+}
+
+// DNSBL rows carry a reason and feed rows a category and network
 #[derive(Serialize)]
 pub struct BlocklistCsvEntry {
     pub ip: String,
     pub dnsbl: String,
-    pub reason: BlocklistReason,
+    // Empty for IP blocklists
+    pub domain: String,
+    pub reason: String,
+    pub category: String,
+    pub network: String,
+    pub score: u32,
+    pub verdict: String,
+    pub abuse_contact: String,
 }
 
 impl ToPlainText for BlocklistRecord {
     fn to_plain_text(&self) -> String {
         let mut result = format!(
-            "IP: {}\nBlocked: {}",
+            "IP: {}\nBlocked: {}\nScore: {} ({})",
             self.ip,
-            if self.blocked { "yes" } else { "no" }
+            if self.blocked { "yes" } else { "no" },
+            self.score,
+            self.verdict
         );
 
         if self.blocked && !self.listed_in.is_empty() {
@@ -45,6 +81,24 @@ impl ToPlainText for BlocklistRecord {
                 result.push_str(&format!("\n - {} ({:?})", entry.dnsbl, entry.reason));
             }
         }
+        if self.blocked && !self.feed_listed_in.is_empty() {
+            result.push_str("\nFeeds:");
+            for listing in &self.feed_listed_in {
+                result.push_str(&format!(
+                    "\n - {} ({}, {})",
+                    listing.feed, listing.category, listing.network
+                ));
+            }
+        }
+        if self.blocked && !self.domain_listed_in.is_empty() {
+            result.push_str(&format!("\nDomain lists ({}):", self.domain));
+            for entry in &self.domain_listed_in {
+                result.push_str(&format!("\n - {} ({:?})", entry.dnsbl, entry.reason));
+            }
+        }
+        if let Some(abuse_contact) = &self.abuse_contact {
+            result.push_str(&format!("\nAbuse contact: {}", abuse_contact.to_plain_text()));
+        }
 
         result
     }
@@ -52,19 +106,41 @@ impl ToPlainText for BlocklistRecord {
 
 impl ToCsv<BlocklistCsvEntry> for BlocklistRecord {
     fn to_csv_entries(&self) -> Vec<BlocklistCsvEntry> {
-        self.listed_in
+        let abuse_contact = self
+            .abuse_contact
+            .as_ref()
+            .map(|contact| contact.email.clone())
+            .unwrap_or_default();
+
+        let dnsbl_entries = self
+            .listed_in
             .iter()
-            .filter_map(|entry| {
-                if entry.reason == BlocklistReason::Unknown {
-                    None
-                } else {
-                    Some(BlocklistCsvEntry {
-                        ip: self.ip.clone(),
-                        dnsbl: entry.dnsbl.clone(),
-                        reason: entry.reason.clone(),
-                    })
-                }
-            })
-            .collect()
+            .map(|entry| (entry, ""))
+            .chain(self.domain_listed_in.iter().map(|entry| (entry, self.domain.as_str())))
+            .filter(|(entry, _)| entry.reason != BlocklistReason::Unknown)
+            .map(|(entry, domain)| BlocklistCsvEntry {
+                ip: self.ip.clone(),
+                dnsbl: entry.dnsbl.clone(),
+                domain: domain.to_string(),
+                reason: format!("{:?}", entry.reason),
+                category: "".to_string(),
+                network: "".to_string(),
+                score: self.score,
+                verdict: self.verdict.clone(),
+                abuse_contact: abuse_contact.clone(),
+            });
+        let feed_entries = self.feed_listed_in.iter().map(|listing| BlocklistCsvEntry {
+            ip: self.ip.clone(),
+            dnsbl: listing.feed.clone(),
+            domain: "".to_string(),
+            reason: "".to_string(),
+            category: listing.category.clone(),
+            network: listing.network.clone(),
+            score: self.score,
+            verdict: self.verdict.clone(),
+            abuse_contact: abuse_contact.clone(),
+        });
+
+        dnsbl_entries.chain(feed_entries).collect()
     }
-} 
\ No newline at end of file
+}
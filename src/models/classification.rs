@@ -0,0 +1,43 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Debug, Serialize, Clone, Default, YaSerialize, JsonSchema)]
+pub struct AddressClassification {
+    pub ip: String,
+    // Whether the IP is in an IANA special-purpose range, range/network/rfc are empty if not
+    pub special: bool,
+    pub range: String,
+    pub network: String,
+    pub rfc: String,
+    pub global: bool,
+    // Reserved-by-Protocol, e.g. loopback, link-local and the limited broadcast address
+    pub reserved: bool,
+    // RFC 1918 and unique-local IPv6 addresses
+    pub private: bool,
+}
+
+impl ToPlainText for AddressClassification {
+    fn to_plain_text(&self) -> String {
+        let range = if self.special {
+            format!("{} ({}, {})", self.range, self.network, self.rfc)
+        } else {
+            "Global Unicast".to_string()
+        };
+
+        format!(
+            "Range: {}\nGlobal: {}\nReserved: {}\nPrivate: {}",
+            range,
+            if self.global { "yes" } else { "no" },
+            if self.reserved { "yes" } else { "no" },
+            if self.private { "yes" } else { "no" },
+        )
+    }
+}
+
+impl ToCsv<AddressClassification> for AddressClassification {
+    fn to_csv_entries(&self) -> Vec<AddressClassification> {
+        vec![self.clone()]
+    }
+}
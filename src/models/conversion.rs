@@ -0,0 +1,85 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct ConvertResponse {
+    pub ip: String,
+    pub version: u8,
+    // Strings, 128 bit values don't survive JSON number parsing
+    pub decimal: String,
+    pub hex: String,
+    pub binary: String,
+    // The IPv4 address itself, or the one embedded in an IPv4-mapped or 6to4 IPv6 address
+    pub ipv4: String,
+    pub ipv4_mapped: String,
+    pub six_to_four: String,
+    pub reverse_dns: String,
+    // Both IPv6 notations, of the IPv4-mapped address for IPv4 input
+    pub expanded: String,
+    pub compressed: String,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct SubnetResponse {
+    pub cidr: String,
+    pub version: u8,
+    pub network: String,
+    // Empty for IPv6, which has no broadcast address
+    pub broadcast: String,
+    pub netmask: String,
+    pub wildcard: String,
+    pub prefix_len: u8,
+    pub first_host: String,
+    pub last_host: String,
+    // Strings like `decimal` above, an IPv6 /0 holds 2^128 addresses
+    pub addresses: String,
+    pub hosts: String,
+}
+
+impl ToPlainText for ConvertResponse {
+    fn to_plain_text(&self) -> String {
+        format!(
+            "IP: {}\nDecimal: {}\nHex: {}\nBinary: {}\nIPv4: {}\nIPv4-mapped: {}\n6to4: {}\nReverse DNS: {}\nExpanded: {}\nCompressed: {}",
+            self.ip,
+            self.decimal,
+            self.hex,
+            self.binary,
+            self.ipv4,
+            self.ipv4_mapped,
+            self.six_to_four,
+            self.reverse_dns,
+            self.expanded,
+            self.compressed,
+        )
+    }
+}
+
+impl ToCsv<ConvertResponse> for ConvertResponse {
+    fn to_csv_entries(&self) -> Vec<ConvertResponse> {
+        vec![self.clone()]
+    }
+}
+
+impl ToPlainText for SubnetResponse {
+    fn to_plain_text(&self) -> String {
+        format!(
+            "Network: {}\nBroadcast: {}\nNetmask: {}\nWildcard: {}\nHosts: {} - {}\nAddresses: {}\nUsable hosts: {}",
+            self.cidr,
+            self.broadcast,
+            self.netmask,
+            self.wildcard,
+            self.first_host,
+            self.last_host,
+            self.addresses,
+            self.hosts,
+        )
+    }
+}
+
+impl ToCsv<SubnetResponse> for SubnetResponse {
+    fn to_csv_entries(&self) -> Vec<SubnetResponse> {
+        vec![self.clone()]
+    }
+}
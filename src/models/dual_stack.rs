@@ -0,0 +1,41 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct DualStackResponse {
+    pub token: String,
+    pub ipv4: String,
+    pub ipv6: String,
+    // Both addresses are known
+    pub complete: bool,
+    pub expires_in: u64,
+    // Where to send the request over the missing address family, empty once complete
+    pub next_url: String,
+}
+
+impl ToPlainText for DualStackResponse {
+    fn to_plain_text(&self) -> String {
+        let mut result = format!(
+            "IPv4: {}\nIPv6: {}",
+            if self.ipv4.is_empty() { "-" } else { &self.ipv4 },
+            if self.ipv6.is_empty() { "-" } else { &self.ipv6 },
+        );
+
+        if !self.next_url.is_empty() {
+            result.push_str(&format!(
+                "\nToken: {} (expires in {} seconds)\nNext: curl {}",
+                self.token, self.expires_in, self.next_url
+            ));
+        }
+
+        result
+    }
+}
+
+impl ToCsv<DualStackResponse> for DualStackResponse {
+    fn to_csv_entries(&self) -> Vec<DualStackResponse> {
+        vec![self.clone()]
+    }
+}
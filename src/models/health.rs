@@ -0,0 +1,94 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Serialize, YaSerialize, JsonSchema)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub databases: Vec<DatabaseStatus>,
+    pub dns: DnsStatus,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct DatabaseStatus {
+    pub name: String,
+    pub loaded: bool,
+    pub database_type: String,
+    pub build_epoch: u64,
+    pub build_date: String,
+    pub age_days: u64,
+    pub healthy: bool,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct DnsStatus {
+    pub canary_query: String,
+    pub healthy: bool,
+    pub duration_ms: u64,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessCsvEntry {
+    pub check: String,
+    pub healthy: bool,
+    pub detail: String,
+}
+
+impl ToPlainText for ReadinessResponse {
+    fn to_plain_text(&self) -> String {
+        let mut result = format!("Ready: {}", if self.ready { "yes" } else { "no" });
+
+        for db in &self.databases {
+            result.push_str(&format!(
+                "\nDatabase {}: {} ({}, built {}, {} days old)",
+                db.name,
+                if db.healthy { "ok" } else { "degraded" },
+                db.database_type,
+                db.build_date,
+                db.age_days
+            ));
+        }
+
+        result.push_str(&format!(
+            "\nDNS: {} ({} in {} ms{})",
+            if self.dns.healthy { "ok" } else { "degraded" },
+            self.dns.canary_query,
+            self.dns.duration_ms,
+            if self.dns.error.is_empty() {
+                "".to_string()
+            } else {
+                format!(", {}", self.dns.error)
+            }
+        ));
+
+        result
+    }
+}
+
+impl ToCsv<ReadinessCsvEntry> for ReadinessResponse {
+    fn to_csv_entries(&self) -> Vec<ReadinessCsvEntry> {
+        let mut entries: Vec<ReadinessCsvEntry> = self
+            .databases
+            .iter()
+            .map(|db| ReadinessCsvEntry {
+                check: format!("database:{}", db.name),
+                healthy: db.healthy,
+                detail: format!("{} built {}", db.database_type, db.build_date),
+            })
+            .collect();
+
+        entries.push(ReadinessCsvEntry {
+            check: "dns".to_string(),
+            healthy: self.dns.healthy,
+            detail: if self.dns.error.is_empty() {
+                format!("{} in {} ms", self.dns.canary_query, self.dns.duration_ms)
+            } else {
+                self.dns.error.clone()
+            },
+        });
+
+        entries
+    }
+}
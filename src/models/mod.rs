@@ -1,11 +1,35 @@
+mod abuse;
 mod all_response;
+mod api_key_usage;
 mod asn;
 mod blocklist;
+mod classification;
+mod conversion;
+mod dual_stack;
+mod health;
+mod network;
+mod port_check;
+mod rdap;
+mod request_info;
 mod simple_response;
+mod traceroute;
 mod traits;
+mod user_agent;
 
+pub use abuse::{AbuseContact, AbuseRecord};
 pub use all_response::{AllResponse};
+pub use api_key_usage::{ApiKeyUsage, ApiKeyUsageResponse, EndpointUsage};
 pub use asn::AsnRecord;
-pub use blocklist::{BlocklistEntry, BlocklistRecord, BlocklistReason};
+pub use blocklist::{BlocklistEntry, BlocklistRecord, BlocklistReason, FeedListing};
+pub use classification::AddressClassification;
+pub use conversion::{ConvertResponse, SubnetResponse};
+pub use dual_stack::DualStackResponse;
+pub use health::{DatabaseStatus, DnsStatus, ReadinessResponse};
+pub use network::NetworkRecord;
+pub use port_check::PortCheckResponse;
+pub use rdap::{RdapContact, RdapRecord};
+pub use request_info::{HeaderEntry, HeadersResponse, RequestResponse, TlsInfo};
 pub use simple_response::SimpleResponse;
-pub use traits::{ToCsv, ToPlainText}; 
\ No newline at end of file
+pub use traceroute::{TracerouteHop, TracerouteResponse};
+pub use traits::{ToCsv, ToPlainText};
+pub use user_agent::UserAgentRecord; 
\ No newline at end of file
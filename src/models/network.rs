@@ -0,0 +1,55 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Debug, Serialize, Clone, Default, YaSerialize, JsonSchema)]
+pub struct NetworkRecord {
+    pub ip: String,
+    // Whether the IP is part of a network from the overlay file, the other fields are empty if not
+    pub matched: bool,
+    pub network: String,
+    pub site: String,
+    pub country: String,
+    pub country_code: String,
+    pub region: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct CsvNetworkEntry {
+    pub ip: String,
+    pub matched: bool,
+    pub network: String,
+    pub site: String,
+    pub country: String,
+    pub country_code: String,
+    pub region: String,
+    pub tags: String,
+}
+
+impl ToPlainText for NetworkRecord {
+    fn to_plain_text(&self) -> String {
+        format!(
+            "Network: {}\nSite: {}\nTags: {}",
+            self.network,
+            self.site,
+            self.tags.join(", "),
+        )
+    }
+}
+
+impl ToCsv<CsvNetworkEntry> for NetworkRecord {
+    fn to_csv_entries(&self) -> Vec<CsvNetworkEntry> {
+        vec![CsvNetworkEntry {
+            ip: self.ip.clone(),
+            matched: self.matched,
+            network: self.network.clone(),
+            site: self.site.clone(),
+            country: self.country.clone(),
+            country_code: self.country_code.clone(),
+            region: self.region.clone(),
+            tags: self.tags.join(";"),
+        }]
+    }
+}
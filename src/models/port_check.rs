@@ -0,0 +1,25 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct PortCheckResponse {
+    pub ip: String,
+    pub port: u16,
+    // open, closed (connection refused) or filtered (no answer within the timeout)
+    pub status: String,
+    pub duration_ms: u64,
+}
+
+impl ToPlainText for PortCheckResponse {
+    fn to_plain_text(&self) -> String {
+        format!("{}:{} {}", self.ip, self.port, self.status)
+    }
+}
+
+impl ToCsv<PortCheckResponse> for PortCheckResponse {
+    fn to_csv_entries(&self) -> Vec<PortCheckResponse> {
+        vec![self.clone()]
+    }
+}
@@ -0,0 +1,95 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Debug, Serialize, Clone, Default, YaSerialize, JsonSchema)]
+pub struct RdapContact {
+    pub handle: String,
+    pub name: String,
+    pub email: String,
+    pub phone: String,
+}
+
+#[derive(Debug, Serialize, Clone, Default, YaSerialize, JsonSchema)]
+pub struct RdapRecord {
+    pub ip: String,
+    pub handle: String,
+    // Network name assigned by the registry, e.g. `GOGL`
+    pub name: String,
+    // CIDR notation when the registry provides it, otherwise the start and end address
+    pub network: String,
+    pub start_address: String,
+    pub end_address: String,
+    // Allocation type, e.g. `DIRECT ALLOCATION` or `ASSIGNED PA`
+    pub allocation_type: String,
+    pub country: String,
+    pub registrant: RdapContact,
+    pub abuse_contact: RdapContact,
+    // RFC 3339 timestamps as published by the registry
+    pub registration_date: String,
+    pub last_changed_date: String,
+    // RDAP URL the record was fetched from
+    pub source: String,
+}
+
+#[derive(Serialize)]
+pub struct CsvRdapEntry {
+    pub ip: String,
+    pub handle: String,
+    pub name: String,
+    pub network: String,
+    pub allocation_type: String,
+    pub country: String,
+    pub registrant: String,
+    pub abuse_email: String,
+    pub registration_date: String,
+    pub last_changed_date: String,
+    pub source: String,
+}
+
+impl ToPlainText for RdapContact {
+    fn to_plain_text(&self) -> String {
+        [&self.name, &self.email, &self.phone]
+            .into_iter()
+            .filter(|value| !value.is_empty())
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+impl ToPlainText for RdapRecord {
+    fn to_plain_text(&self) -> String {
+        format!(
+            "Network: {} ({})\nName: {}\nType: {}\nCountry: {}\nRegistrant: {}\nAbuse contact: {}\nRegistered: {}\nLast changed: {}",
+            self.network,
+            self.handle,
+            self.name,
+            self.allocation_type,
+            self.country,
+            self.registrant.to_plain_text(),
+            self.abuse_contact.to_plain_text(),
+            self.registration_date,
+            self.last_changed_date,
+        )
+    }
+}
+
+impl ToCsv<CsvRdapEntry> for RdapRecord {
+    fn to_csv_entries(&self) -> Vec<CsvRdapEntry> {
+        vec![CsvRdapEntry {
+            ip: self.ip.clone(),
+            handle: self.handle.clone(),
+            name: self.name.clone(),
+            network: self.network.clone(),
+            allocation_type: self.allocation_type.clone(),
+            country: self.country.clone(),
+            registrant: self.registrant.name.clone(),
+            abuse_email: self.abuse_contact.email.clone(),
+            registration_date: self.registration_date.clone(),
+            last_changed_date: self.last_changed_date.clone(),
+            source: self.source.clone(),
+        }]
+    }
+}
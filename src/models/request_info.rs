@@ -0,0 +1,130 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Serialize, YaSerialize, JsonSchema)]
+pub struct HeadersResponse {
+    pub headers: Vec<HeaderEntry>,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct HeaderEntry {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema)]
+pub struct RequestResponse {
+    pub method: String,
+    pub version: String,
+    pub path: String,
+    pub query: String,
+    pub client_ip: String,
+    // Where `client_ip` was taken from: x-forwarded-for, x-real-ip or peer
+    pub client_ip_source: String,
+    pub peer_addr: String,
+    pub proxy_chain: Vec<String>,
+    pub tls: TlsInfo,
+    pub headers: Vec<HeaderEntry>,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct TlsInfo {
+    pub enabled: bool,
+    // `ipeek` for native TLS listeners, `proxy` when the scheme was reported by a reverse proxy,
+    // `none` for plain HTTP
+    pub terminated_by: String,
+    // Session details, only known when ipeek terminated the connection
+    pub version: String,
+    pub cipher_suite: String,
+    pub alpn: String,
+    pub sni: String,
+}
+
+#[derive(Serialize)]
+pub struct RequestCsvEntry {
+    pub field: String,
+    pub value: String,
+}
+
+impl ToPlainText for HeadersResponse {
+    fn to_plain_text(&self) -> String {
+        self.headers
+            .iter()
+            .map(|header| format!("{}: {}", header.name, header.value))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl ToCsv<HeaderEntry> for HeadersResponse {
+    fn to_csv_entries(&self) -> Vec<HeaderEntry> {
+        self.headers.clone()
+    }
+}
+
+impl ToPlainText for RequestResponse {
+    fn to_plain_text(&self) -> String {
+        let mut result = format!(
+            "Method: {}\nVersion: {}\nPath: {}\nQuery: {}\nClient IP: {} ({})\nPeer: {}\nProxy chain: {}\nTLS: {}\nHeaders:",
+            self.method,
+            self.version,
+            self.path,
+            self.query,
+            self.client_ip,
+            self.client_ip_source,
+            self.peer_addr,
+            self.proxy_chain.join(" -> "),
+            if self.tls.terminated_by == "ipeek" {
+                format!(
+                    "yes ({}, {}, ALPN {}, SNI {})",
+                    self.tls.version, self.tls.cipher_suite, self.tls.alpn, self.tls.sni
+                )
+            } else if self.tls.enabled {
+                format!("yes (terminated by {})", self.tls.terminated_by)
+            } else {
+                "no".to_string()
+            },
+        );
+
+        for header in &self.headers {
+            result.push_str(&format!("\n - {}: {}", header.name, header.value));
+        }
+
+        result
+    }
+}
+
+impl ToCsv<RequestCsvEntry> for RequestResponse {
+    fn to_csv_entries(&self) -> Vec<RequestCsvEntry> {
+        let fields = [
+            ("method", self.method.clone()),
+            ("version", self.version.clone()),
+            ("path", self.path.clone()),
+            ("query", self.query.clone()),
+            ("client_ip", self.client_ip.clone()),
+            ("client_ip_source", self.client_ip_source.clone()),
+            ("peer_addr", self.peer_addr.clone()),
+            ("proxy_chain", self.proxy_chain.join(" ")),
+            ("tls", self.tls.enabled.to_string()),
+            ("tls_terminated_by", self.tls.terminated_by.clone()),
+            ("tls_version", self.tls.version.clone()),
+            ("tls_cipher_suite", self.tls.cipher_suite.clone()),
+            ("tls_alpn", self.tls.alpn.clone()),
+            ("tls_sni", self.tls.sni.clone()),
+        ];
+
+        fields
+            .into_iter()
+            .map(|(field, value)| RequestCsvEntry {
+                field: field.to_string(),
+                value,
+            })
+            .chain(self.headers.iter().map(|header| RequestCsvEntry {
+                field: format!("header:{}", header.name),
+                value: header.value.clone(),
+            }))
+            .collect()
+    }
+}
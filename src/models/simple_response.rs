@@ -1,8 +1,9 @@
+use schemars::JsonSchema;
 use serde::Serialize;
 use yaserde_derive::YaSerialize;
 use crate::models::traits::{ToCsv, ToPlainText};
 
-#[derive(Serialize, YaSerialize)]
+#[derive(Serialize, YaSerialize, JsonSchema)]
 pub struct SimpleResponse {
     pub value: String,
 }
@@ -0,0 +1,77 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Serialize, YaSerialize, JsonSchema)]
+pub struct TracerouteResponse {
+    pub target: String,
+    pub max_hops: u8,
+    pub status: String,
+    pub duration_ms: u64,
+    pub hops: Vec<TracerouteHop>,
+    pub error: String,
+}
+
+#[derive(Serialize, YaSerialize, JsonSchema, Clone)]
+pub struct TracerouteHop {
+    pub hop: u8,
+    pub ip: String,
+    pub hostname: String,
+    pub rtt_ms: f64,
+    pub asn: u32,
+    pub aso: String,
+    pub country_code: String,
+}
+
+impl TracerouteResponse {
+    pub fn header(target: &str, max_hops: u8) -> String {
+        format!("traceroute to {}, {} hops max", target, max_hops)
+    }
+
+    pub fn summary(&self) -> String {
+        if self.error.is_empty() {
+            format!("Status: {} ({} ms)", self.status, self.duration_ms)
+        } else {
+            format!("Status: {} ({})", self.status, self.error)
+        }
+    }
+}
+
+impl ToPlainText for TracerouteHop {
+    fn to_plain_text(&self) -> String {
+        format!(
+            "{:>2}  {}  {}  {:.2} ms  AS{} {} [{}]",
+            self.hop,
+            self.ip,
+            if self.hostname.is_empty() {
+                &self.ip
+            } else {
+                &self.hostname
+            },
+            self.rtt_ms,
+            self.asn,
+            self.aso,
+            self.country_code
+        )
+    }
+}
+
+impl ToPlainText for TracerouteResponse {
+    fn to_plain_text(&self) -> String {
+        let mut result = Self::header(&self.target, self.max_hops);
+        for hop in &self.hops {
+            result.push('\n');
+            result.push_str(&hop.to_plain_text());
+        }
+        result.push('\n');
+        result.push_str(&self.summary());
+        result
+    }
+}
+
+impl ToCsv<TracerouteHop> for TracerouteResponse {
+    fn to_csv_entries(&self) -> Vec<TracerouteHop> {
+        self.hops.clone()
+    }
+}
@@ -0,0 +1,34 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use yaserde_derive::YaSerialize;
+use crate::models::traits::{ToCsv, ToPlainText};
+
+#[derive(Debug, Serialize, Clone, Default, YaSerialize, JsonSchema)]
+pub struct UserAgentRecord {
+    pub user_agent: String,
+    pub browser: String,
+    pub browser_version: String,
+    pub os: String,
+    pub os_version: String,
+    // desktop, mobile, appliance, cli, api-client, bot or unknown
+    pub device: String,
+    pub bot: bool,
+}
+
+impl ToPlainText for UserAgentRecord {
+    fn to_plain_text(&self) -> String {
+        format!(
+            "Browser: {}\nOS: {}\nDevice: {}\nBot: {}",
+            format!("{} {}", self.browser, self.browser_version).trim(),
+            format!("{} {}", self.os, self.os_version).trim(),
+            self.device,
+            if self.bot { "yes" } else { "no" },
+        )
+    }
+}
+
+impl ToCsv<UserAgentRecord> for UserAgentRecord {
+    fn to_csv_entries(&self) -> Vec<UserAgentRecord> {
+        vec![self.clone()]
+    }
+}
@@ -0,0 +1,306 @@
+use crate::config::NetworkConfig;
+use crate::models::{AsnRecord, NetworkRecord};
+use crate::prefix_tree::{parse_network, PrefixTree};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+struct OverlayFile {
+    #[serde(default)]
+    networks: Vec<NetworkDefinition>,
+}
+
+// One network of a YAML (or TOML/JSON) overlay, fields left out fall through to MaxMind
+#[derive(Debug, Deserialize)]
+struct NetworkDefinition {
+    network: String,
+    site: Option<String>,
+    country: Option<String>,
+    country_code: Option<String>,
+    region: Option<String>,
+    asn: Option<u32>,
+    organization: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// CSV rows carry the tags in a single `;` separated column
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    network: String,
+    site: Option<String>,
+    country: Option<String>,
+    country_code: Option<String>,
+    region: Option<String>,
+    asn: Option<u32>,
+    organization: Option<String>,
+    tags: Option<String>,
+}
+
+impl From<CsvRow> for NetworkDefinition {
+    fn from(row: CsvRow) -> Self {
+        Self {
+            network: row.network,
+            site: row.site,
+            country: row.country,
+            country_code: row.country_code,
+            region: row.region,
+            asn: row.asn,
+            organization: row.organization,
+            tags: row
+                .tags
+                .unwrap_or_default()
+                .split(';')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        }
+    }
+}
+
+// Internal networks and their location, consulted before the MaxMind databases
+#[derive(Default)]
+pub struct NetworkOverlay {
+    networks: PrefixTree<NetworkDefinition>,
+}
+
+impl NetworkOverlay {
+    pub fn load(config: &NetworkConfig) -> Result<Self, String> {
+        let Some(path) = &config.overlay_path else {
+            return Ok(Self::default());
+        };
+
+        let is_csv = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let definitions = if is_csv {
+            read_csv(path)?
+        } else {
+            config::Config::builder()
+                .add_source(config::File::with_name(path))
+                .build()
+                .and_then(|file| file.try_deserialize::<OverlayFile>())
+                .map_err(|err| err.to_string())?
+                .networks
+        };
+
+        let mut networks = PrefixTree::default();
+        for definition in definitions {
+            let network = parse_network(&definition.network)
+                .ok_or_else(|| format!("`{}` is not a network", definition.network))?;
+            networks.insert(network, definition);
+        }
+        Ok(Self { networks })
+    }
+
+    pub fn len(&self) -> usize {
+        self.networks.len()
+    }
+
+    // Fields a network leaves out are inherited from the networks containing it, tags are merged
+    pub fn lookup(&self, ip: IpAddr) -> NetworkRecord {
+        let matches = self.networks.matches(ip);
+        let Some((network, _)) = matches.first() else {
+            return NetworkRecord {
+                ip: ip.to_string(),
+                ..Default::default()
+            };
+        };
+
+        let mut tags: Vec<String> = Vec::new();
+        for (_, definition) in &matches {
+            for tag in &definition.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+
+        NetworkRecord {
+            ip: ip.to_string(),
+            matched: true,
+            network: network.to_string(),
+            site: self
+                .field(ip, |definition| &definition.site)
+                .unwrap_or_default(),
+            country: self.country(ip).unwrap_or_default(),
+            country_code: self.country_code(ip).unwrap_or_default(),
+            region: self.region(ip).unwrap_or_default(),
+            tags,
+        }
+    }
+
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        self.field(ip, |definition| &definition.country)
+    }
+
+    pub fn country_code(&self, ip: IpAddr) -> Option<String> {
+        self.field(ip, |definition| &definition.country_code)
+    }
+
+    pub fn region(&self, ip: IpAddr) -> Option<String> {
+        self.field(ip, |definition| &definition.region)
+    }
+
+    // Only overrides the ASN lookup when the network defines an ASN or organization
+    pub fn asn(&self, ip: IpAddr) -> Option<AsnRecord> {
+        let asn = self
+            .networks
+            .matches(ip)
+            .into_iter()
+            .find_map(|(_, definition)| definition.asn);
+        let organization = self.field(ip, |definition| &definition.organization);
+        if asn.is_none() && organization.is_none() {
+            return None;
+        }
+        Some(AsnRecord {
+            asn: asn.or(Some(0)),
+            aso: organization.or(Some("".to_string())),
+        })
+    }
+
+    fn field(
+        &self,
+        ip: IpAddr,
+        field: impl Fn(&NetworkDefinition) -> &Option<String>,
+    ) -> Option<String> {
+        self.networks
+            .matches(ip)
+            .into_iter()
+            .find_map(|(_, definition)| field(definition).clone())
+    }
+}
+
+fn read_csv(path: &str) -> Result<Vec<NetworkDefinition>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .comment(Some(b'#'))
+        .from_path(path)
+        .map_err(|err| err.to_string())?;
+
+    reader
+        .deserialize::<CsvRow>()
+        .map(|row| {
+            row.map(NetworkDefinition::from)
+                .map_err(|err| err.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = r#"
+networks:
+  - network: 10.0.0.0/8
+    country: Germany
+    country_code: DE
+    asn: 64500
+    organization: Example Corp
+    tags: [internal]
+  - network: 10.1.0.0/16
+    site: Berlin
+    region: Berlin
+    tags: [office, internal]
+  - network: 10.1.2.0/24
+    site: Berlin Lab
+    tags: [lab]
+  - network: 172.16.0.1
+    site: Gateway
+"#;
+
+    const CSV: &str = "\
+network,site,country,country_code,region,asn,organization,tags
+# Home network
+192.168.0.0/16,Home,,,,,,wifi; guests
+192.168.1.0/24,Office,Austria,AT,Vienna,,,wired;wifi
+";
+
+    fn load(name: &str, contents: &str) -> Result<NetworkOverlay, String> {
+        let path = std::env::temp_dir().join(format!("ipeek-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let overlay = NetworkOverlay::load(&NetworkConfig {
+            overlay_path: Some(path.to_string_lossy().into_owned()),
+        });
+        std::fs::remove_file(&path).unwrap();
+        overlay
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn lookup_inherits_fields_and_merges_tags() {
+        let overlay = load("overlay.yaml", YAML).unwrap();
+        assert_eq!(overlay.len(), 4);
+
+        let record = overlay.lookup(ip("10.1.2.3"));
+        assert!(record.matched);
+        assert_eq!(record.network, "10.1.2.0/24");
+        assert_eq!(record.site, "Berlin Lab");
+        assert_eq!(record.region, "Berlin");
+        assert_eq!(
+            (record.country.as_str(), record.country_code.as_str()),
+            ("Germany", "DE")
+        );
+        assert_eq!(record.tags, ["lab", "office", "internal"]);
+
+        let record = overlay.lookup(ip("10.2.0.1"));
+        assert_eq!(record.network, "10.0.0.0/8");
+        assert_eq!((record.site.as_str(), record.region.as_str()), ("", ""));
+        assert_eq!(record.tags, ["internal"]);
+    }
+
+    #[test]
+    fn lookup_outside_the_overlay() {
+        let overlay = load("outside.yaml", YAML).unwrap();
+        let record = overlay.lookup(ip("192.0.2.1"));
+        assert!(!record.matched);
+        assert_eq!(record.ip, "192.0.2.1");
+        assert!(record.network.is_empty() && record.tags.is_empty());
+        assert_eq!(overlay.country(ip("192.0.2.1")), None);
+    }
+
+    #[test]
+    fn asn_is_inherited_and_only_set_when_defined() {
+        let overlay = load("asn.yaml", YAML).unwrap();
+        let asn = overlay.asn(ip("10.1.2.3")).unwrap();
+        assert_eq!(
+            (asn.asn, asn.aso.as_deref()),
+            (Some(64500), Some("Example Corp"))
+        );
+        assert!(overlay.asn(ip("172.16.0.1")).is_none());
+        assert!(overlay.asn(ip("192.0.2.1")).is_none());
+    }
+
+    #[test]
+    fn load_csv_splits_tags() {
+        let overlay = load("overlay.csv", CSV).unwrap();
+        assert_eq!(overlay.len(), 2);
+
+        let record = overlay.lookup(ip("192.168.1.1"));
+        assert_eq!(
+            (record.site.as_str(), record.region.as_str()),
+            ("Office", "Vienna")
+        );
+        assert_eq!(record.tags, ["wired", "wifi", "guests"]);
+
+        let record = overlay.lookup(ip("192.168.2.1"));
+        assert_eq!(
+            (record.site.as_str(), record.country.as_str()),
+            ("Home", "")
+        );
+        assert_eq!(record.tags, ["wifi", "guests"]);
+    }
+
+    #[test]
+    fn load_rejects_invalid_networks() {
+        let err = load("invalid.yaml", "networks:\n  - network: 10.0.0.0/33\n")
+            .err()
+            .unwrap();
+        assert_eq!(err, "`10.0.0.0/33` is not a network");
+    }
+}
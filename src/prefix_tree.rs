@@ -0,0 +1,298 @@
+use ipnet::{IpNet, Ipv4Subnets, Ipv6Subnets};
+use std::net::IpAddr;
+
+// Binary trie over the address bits answering longest-prefix matches. IPv4 and IPv6 networks
+// live in separate roots, so `0.0.0.0/0` never matches an IPv6 address
+pub struct PrefixTree<T> {
+    v4: Node<T>,
+    v6: Node<T>,
+    len: usize,
+}
+
+struct Node<T> {
+    value: Option<(IpNet, T)>,
+    children: [Option<Box<Node<T>>>; 2],
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            children: [None, None],
+        }
+    }
+}
+
+impl<T> Default for PrefixTree<T> {
+    fn default() -> Self {
+        Self {
+            v4: Node::default(),
+            v6: Node::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> PrefixTree<T> {
+    // Host bits of `network` are ignored, inserting the same network twice replaces the value
+    pub fn insert(&mut self, network: IpNet, value: T) {
+        let network = network.trunc();
+        let bits = address_bits(network.addr());
+        let mut node = match network {
+            IpNet::V4(_) => &mut self.v4,
+            IpNet::V6(_) => &mut self.v6,
+        };
+
+        for index in 0..network.prefix_len() {
+            node = node.children[bit(bits, index)].get_or_insert_with(Box::default);
+        }
+        if node.value.replace((network, value)).is_none() {
+            self.len += 1;
+        }
+    }
+
+    // Most specific network containing `ip`
+    pub fn longest_match(&self, ip: IpAddr) -> Option<(&IpNet, &T)> {
+        self.matches(ip).into_iter().next()
+    }
+
+    // Every network containing `ip`, most specific first. IPv4-mapped IPv6 addresses are
+    // matched as IPv4
+    pub fn matches(&self, ip: IpAddr) -> Vec<(&IpNet, &T)> {
+        let ip = ip.to_canonical();
+        let bits = address_bits(ip);
+        let (mut node, max_len) = match ip {
+            IpAddr::V4(_) => (&self.v4, 32),
+            IpAddr::V6(_) => (&self.v6, 128),
+        };
+
+        let mut matches = Vec::new();
+        matches.extend(node.value.as_ref().map(|(network, value)| (network, value)));
+        for index in 0..max_len {
+            match &node.children[bit(bits, index)] {
+                Some(child) => node = child,
+                None => break,
+            }
+            matches.extend(node.value.as_ref().map(|(network, value)| (network, value)));
+        }
+        matches.reverse();
+        matches
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // Removes the networks whose value `keep` rejects
+    pub fn retain(&mut self, mut keep: impl FnMut(&IpNet, &T) -> bool) {
+        let removed = retain_node(&mut self.v4, &mut keep) + retain_node(&mut self.v6, &mut keep);
+        self.len -= removed;
+    }
+}
+
+// Number of removed values, branches left without any value are dropped
+fn retain_node<T>(node: &mut Node<T>, keep: &mut impl FnMut(&IpNet, &T) -> bool) -> usize {
+    let mut removed = 0;
+    if matches!(&node.value, Some((network, value)) if !keep(network, value)) {
+        node.value = None;
+        removed += 1;
+    }
+
+    for child in &mut node.children {
+        if let Some(inner) = child {
+            removed += retain_node(inner, keep);
+            if inner.value.is_none() && inner.children.iter().all(Option::is_none) {
+                *child = None;
+            }
+        }
+    }
+    removed
+}
+
+// Address left-aligned in 128 bits, so bit 0 is the most significant bit for both families
+fn address_bits(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(u32::from(ip)) << 96,
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+fn bit(bits: u128, index: u8) -> usize {
+    ((bits >> (127 - index)) & 1) as usize
+}
+
+// CIDR notation, a single address counts as a host route
+pub fn parse_network(network: &str) -> Option<IpNet> {
+    network
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| network.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+// `inetnum` style ranges (`192.0.2.0 - 192.0.2.255`) as the networks covering them, or CIDR
+// notation
+pub fn parse_range(network: &str) -> Option<Vec<IpNet>> {
+    if let Ok(network) = network.parse::<IpNet>() {
+        return Some(vec![network.trunc()]);
+    }
+
+    let (start, end) = network.split_once('-')?;
+    match (
+        start.trim().parse::<IpAddr>().ok()?,
+        end.trim().parse::<IpAddr>().ok()?,
+    ) {
+        (IpAddr::V4(start), IpAddr::V4(end)) if start <= end => {
+            Some(Ipv4Subnets::new(start, end, 0).map(IpNet::V4).collect())
+        }
+        (IpAddr::V6(start), IpAddr::V6(end)) if start <= end => {
+            Some(Ipv6Subnets::new(start, end, 0).map(IpNet::V6).collect())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(network: &str) -> IpNet {
+        network.parse().unwrap()
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn tree(networks: &[&str]) -> PrefixTree<()> {
+        let mut tree = PrefixTree::default();
+        for network in networks {
+            tree.insert(net(network), ());
+        }
+        tree
+    }
+
+    fn longest(tree: &PrefixTree<()>, address: &str) -> Option<String> {
+        tree.longest_match(ip(address))
+            .map(|(network, _)| network.to_string())
+    }
+
+    #[test]
+    fn longest_match_prefers_the_most_specific_network() {
+        let tree = tree(&["10.0.0.0/8", "10.1.0.0/16", "10.1.2.0/24"]);
+        assert_eq!(longest(&tree, "10.1.2.3").as_deref(), Some("10.1.2.0/24"));
+        assert_eq!(longest(&tree, "10.1.3.1").as_deref(), Some("10.1.0.0/16"));
+        assert_eq!(
+            longest(&tree, "10.255.255.255").as_deref(),
+            Some("10.0.0.0/8")
+        );
+        assert_eq!(longest(&tree, "11.0.0.0"), None);
+
+        let matches: Vec<String> = tree
+            .matches(ip("10.1.2.3"))
+            .iter()
+            .map(|(network, _)| network.to_string())
+            .collect();
+        assert_eq!(matches, ["10.1.2.0/24", "10.1.0.0/16", "10.0.0.0/8"]);
+    }
+
+    #[test]
+    fn default_routes_and_host_routes() {
+        let tree = tree(&["0.0.0.0/0", "192.0.2.1/32", "::/0", "2001:db8::1/128"]);
+        assert_eq!(longest(&tree, "192.0.2.1").as_deref(), Some("192.0.2.1/32"));
+        assert_eq!(longest(&tree, "192.0.2.2").as_deref(), Some("0.0.0.0/0"));
+        assert_eq!(
+            longest(&tree, "255.255.255.255").as_deref(),
+            Some("0.0.0.0/0")
+        );
+        assert_eq!(
+            longest(&tree, "2001:db8::1").as_deref(),
+            Some("2001:db8::1/128")
+        );
+        assert_eq!(longest(&tree, "2001:db8::2").as_deref(), Some("::/0"));
+    }
+
+    #[test]
+    fn families_are_kept_apart() {
+        let v4 = tree(&["0.0.0.0/0"]);
+        assert_eq!(longest(&v4, "::"), None);
+        assert_eq!(longest(&v4, "2001:db8::1"), None);
+
+        let v6 = tree(&["::/0"]);
+        assert_eq!(longest(&v6, "0.0.0.0"), None);
+        assert_eq!(longest(&v6, "192.0.2.1"), None);
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_match_ipv4_networks() {
+        let tree = tree(&["192.0.2.0/24", "::ffff:0:0/96"]);
+        assert_eq!(
+            longest(&tree, "::ffff:192.0.2.1").as_deref(),
+            Some("192.0.2.0/24")
+        );
+        assert_eq!(longest(&tree, "::ffff:198.51.100.1"), None);
+    }
+
+    #[test]
+    fn insert_ignores_host_bits_and_replaces() {
+        let mut tree = PrefixTree::default();
+        tree.insert(net("192.0.2.77/24"), 1);
+        tree.insert(net("192.0.2.0/24"), 2);
+        assert_eq!(tree.len(), 1);
+        let (network, value) = tree.longest_match(ip("192.0.2.1")).unwrap();
+        assert_eq!((network.to_string().as_str(), *value), ("192.0.2.0/24", 2));
+    }
+
+    #[test]
+    fn retain_removes_rejected_networks() {
+        let mut tree = tree(&["10.0.0.0/8", "10.1.0.0/16", "10.1.2.0/24", "2001:db8::/32"]);
+        tree.retain(|network, _| network.prefix_len() != 16);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(longest(&tree, "10.1.3.1").as_deref(), Some("10.0.0.0/8"));
+        assert_eq!(longest(&tree, "10.1.2.3").as_deref(), Some("10.1.2.0/24"));
+
+        tree.retain(|network, _| network.addr().is_ipv6());
+        assert_eq!(tree.len(), 1);
+        assert_eq!(longest(&tree, "10.1.2.3"), None);
+        assert!(tree.v4.children.iter().all(Option::is_none));
+        assert_eq!(
+            longest(&tree, "2001:db8::1").as_deref(),
+            Some("2001:db8::/32")
+        );
+    }
+
+    #[test]
+    fn parse_network_accepts_cidr_and_addresses() {
+        assert_eq!(parse_network("192.0.2.0/24"), Some(net("192.0.2.0/24")));
+        assert_eq!(parse_network("192.0.2.1"), Some(net("192.0.2.1/32")));
+        assert_eq!(parse_network("2001:db8::1"), Some(net("2001:db8::1/128")));
+        assert_eq!(parse_network("192.0.2.0/33"), None);
+        assert_eq!(parse_network("example.com"), None);
+    }
+
+    #[test]
+    fn parse_range_covers_inetnum_ranges() {
+        assert_eq!(
+            parse_range("192.0.2.0 - 192.0.2.255"),
+            Some(vec![net("192.0.2.0/24")])
+        );
+        assert_eq!(
+            parse_range("192.0.2.0 - 192.0.3.127"),
+            Some(vec![net("192.0.2.0/24"), net("192.0.3.0/25")])
+        );
+        assert_eq!(
+            parse_range("192.0.2.7-192.0.2.7"),
+            Some(vec![net("192.0.2.7/32")])
+        );
+        assert_eq!(
+            parse_range("2001:db8:: - 2001:db8::ffff"),
+            Some(vec![net("2001:db8::/112")])
+        );
+        assert_eq!(
+            parse_range("192.0.2.77/24"),
+            Some(vec![net("192.0.2.0/24")])
+        );
+        assert_eq!(parse_range("192.0.2.255 - 192.0.2.0"), None);
+        assert_eq!(parse_range("192.0.2.0 - 2001:db8::"), None);
+        assert_eq!(parse_range("not a range"), None);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::api_keys::ApiKeyIdentity;
+use crate::config::{RateLimitBudget, RateLimitConfig};
+use crate::handlers::ENDPOINTS;
+use crate::util::{get_trusted_ip, is_browser, matched_pattern};
+use crate::AppState;
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+// Most clients a budget tracks, full buckets are pruned and then the least recently used
+// evicted once a new client would exceed it
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitTier {
+    // Answered from local data (MaxMind lookups, request data)
+    Cheap,
+    // Fans out to DNS lookups, e.g. reverse DNS and DNSBL queries
+    Expensive,
+    // Never limited, e.g. health checks
+    Exempt,
+}
+
+pub struct RateLimiter {
+    cheap: Option<TokenBuckets>,
+    expensive: Option<TokenBuckets>,
+}
+
+struct TokenBuckets {
+    budget: TokenBucketBudget,
+    max_entries: usize,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+#[derive(Clone, Copy)]
+pub struct TokenBucketBudget {
+    capacity: f64,
+    refill_per_second: f64,
+}
+
+pub struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Seconds until the bucket is full again
+    pub reset: u64,
+    // Seconds until the next request is allowed
+    pub retry_after: u64,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let buckets = |budget: &RateLimitBudget| {
+            (config.enabled && budget.requests_per_minute > 0)
+                .then(|| TokenBuckets::new(TokenBucketBudget::new(budget), MAX_BUCKETS))
+        };
+
+        Self {
+            cheap: buckets(&config.cheap),
+            expensive: buckets(&config.expensive),
+        }
+    }
+
+    pub fn check(&self, tier: RateLimitTier, ip: IpAddr) -> Option<RateLimitDecision> {
+        let buckets = match tier {
+            RateLimitTier::Cheap => self.cheap.as_ref(),
+            RateLimitTier::Expensive => self.expensive.as_ref(),
+            RateLimitTier::Exempt => None,
+        };
+        buckets.map(|buckets| buckets.take(ip, Instant::now()))
+    }
+}
+
+impl TokenBuckets {
+    fn new(budget: TokenBucketBudget, max_entries: usize) -> Self {
+        Self {
+            budget,
+            max_entries,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn take(&self, ip: IpAddr, now: Instant) -> RateLimitDecision {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= self.max_entries && !buckets.contains_key(&ip) {
+            self.prune(&mut buckets, now);
+        }
+
+        buckets
+            .entry(ip)
+            .or_insert_with(|| TokenBucket::new(&self.budget))
+            .take(&self.budget, now)
+    }
+
+    fn prune(&self, buckets: &mut HashMap<IpAddr, TokenBucket>, now: Instant) {
+        // Full buckets hold no state worth keeping
+        buckets.retain(|_, bucket| !bucket.is_full(&self.budget, now));
+        if buckets.len() < self.max_entries {
+            return;
+        }
+
+        // Evict the least recently used tenth at once rather than one bucket per new client
+        let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+        let evict = (self.max_entries / 10).max(1);
+        let cutoff = *updated.select_nth_unstable(evict - 1).1;
+        buckets.retain(|_, bucket| bucket.updated > cutoff);
+    }
+}
+
+impl TokenBucketBudget {
+    pub fn new(budget: &RateLimitBudget) -> Self {
+        Self {
+            capacity: budget.burst.max(1) as f64,
+            refill_per_second: budget.requests_per_minute as f64 / 60.0,
+        }
+    }
+}
+
+impl TokenBucket {
+    pub fn new(budget: &TokenBucketBudget) -> Self {
+        Self {
+            tokens: budget.capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    pub fn take(&mut self, budget: &TokenBucketBudget, now: Instant) -> RateLimitDecision {
+        self.tokens = self.refilled(budget, now);
+        self.updated = now;
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        RateLimitDecision {
+            allowed,
+            limit: budget.capacity as u32,
+            remaining: self.tokens.floor() as u32,
+            reset: ((budget.capacity - self.tokens) / budget.refill_per_second).ceil() as u64,
+            retry_after: if allowed {
+                0
+            } else {
+                ((1.0 - self.tokens) / budget.refill_per_second).ceil() as u64
+            },
+        }
+    }
+
+    fn is_full(&self, budget: &TokenBucketBudget, now: Instant) -> bool {
+        self.refilled(budget, now) >= budget.capacity
+    }
+
+    fn refilled(&self, budget: &TokenBucketBudget, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * budget.refill_per_second).min(budget.capacity)
+    }
+}
+
+// Middleware enforcing the rate limit tier of the matched endpoint or the API key quota,
+// must run after `FormatMiddleware` and `ApiKeyMiddleware`
+pub struct RateLimitMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimitMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddlewareService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimitMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let tier = endpoint_tier(&req);
+            let identity = req.extensions().get::<ApiKeyIdentity>().cloned();
+            let decision = req
+                .app_data::<web::Data<Arc<AppState>>>()
+                .and_then(|state| match (tier, identity) {
+                    (RateLimitTier::Exempt, _) => None,
+                    // Requests with an API key are limited by the key's quota instead of the IP
+                    (_, Some(identity)) => state.api_keys.check_quota(&identity),
+                    (tier, None) => state
+                        .rate_limiter
+                        .check(tier, get_trusted_ip(req.request(), &state.trusted_proxies)),
+                });
+
+            let decision = match decision {
+                Some(decision) => decision,
+                None => return Ok(service.call(req).await?.map_into_left_body()),
+            };
+
+            if !decision.allowed {
+                let mut response = HttpResponse::TooManyRequests()
+                    .content_type("text/plain")
+                    .insert_header((RETRY_AFTER, decision.retry_after.to_string()))
+                    .body(format!(
+                        "Rate limit exceeded, retry in {} seconds\n",
+                        decision.retry_after
+                    ));
+                insert_rate_limit_headers(response.headers_mut(), &decision);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            let mut res = service.call(req).await?;
+            insert_rate_limit_headers(res.headers_mut(), &decision);
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+fn endpoint_tier(req: &ServiceRequest) -> RateLimitTier {
+    let pattern = matched_pattern(req.request());
+
+    // `/` renders the documentation for browsers, which costs as much as `/docs`
+    if pattern.as_deref() == Some("/") && is_browser(req.request()) {
+        return RateLimitTier::Expensive;
+    }
+
+    ENDPOINTS
+        .iter()
+        .find(|endpoint| pattern.as_deref() == Some(endpoint.path))
+        .map(|endpoint| endpoint.rate_limit)
+        .unwrap_or(RateLimitTier::Cheap)
+}
+
+fn insert_rate_limit_headers(
+    headers: &mut actix_web::http::header::HeaderMap,
+    decision: &RateLimitDecision,
+) {
+    for (name, value) in [
+        ("ratelimit-limit", decision.limit as u64),
+        ("ratelimit-remaining", decision.remaining as u64),
+        ("ratelimit-reset", decision.reset),
+    ] {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn budget(requests_per_minute: u32, burst: u32) -> TokenBucketBudget {
+        TokenBucketBudget::new(&RateLimitBudget {
+            requests_per_minute,
+            burst,
+        })
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 0, 2, last])
+    }
+
+    #[test]
+    fn take_drains_the_burst_and_refills() {
+        let budget = budget(60, 2);
+        let mut bucket = TokenBucket::new(&budget);
+        let start = bucket.updated;
+
+        let first = bucket.take(&budget, start);
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining), (2, 1));
+        assert!(bucket.take(&budget, start).allowed);
+        assert!(!bucket.take(&budget, start).allowed);
+
+        let refilled = bucket.take(&budget, start + Duration::from_secs(1));
+        assert!(refilled.allowed);
+        assert_eq!(refilled.remaining, 0);
+    }
+
+    #[test]
+    fn take_caps_the_refill_at_the_burst() {
+        let budget = budget(60, 2);
+        let mut bucket = TokenBucket::new(&budget);
+        let start = bucket.updated;
+
+        bucket.take(&budget, start);
+        bucket.take(&budget, start);
+        let decision = bucket.take(&budget, start + Duration::from_secs(3600));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 1);
+    }
+
+    #[test]
+    fn take_reports_reset_and_retry_after() {
+        // One token every two seconds
+        let budget = budget(30, 3);
+        let mut bucket = TokenBucket::new(&budget);
+        let start = bucket.updated;
+
+        let first = bucket.take(&budget, start);
+        assert_eq!((first.reset, first.retry_after), (2, 0));
+        bucket.take(&budget, start);
+        let last = bucket.take(&budget, start);
+        assert!(last.allowed);
+        assert_eq!((last.remaining, last.reset, last.retry_after), (0, 6, 0));
+
+        let denied = bucket.take(&budget, start + Duration::from_secs(1));
+        assert!(!denied.allowed);
+        assert_eq!((denied.reset, denied.retry_after), (5, 1));
+    }
+
+    #[test]
+    fn take_prunes_full_buckets_for_new_clients() {
+        let buckets = TokenBuckets::new(budget(60, 2), 3);
+        let start = Instant::now();
+
+        for last in 1..=3 {
+            buckets.take(ip(last), start);
+        }
+        buckets.take(ip(4), start + Duration::from_secs(10));
+
+        let tracked = buckets.buckets.lock().unwrap();
+        assert_eq!(tracked.len(), 1);
+        assert!(tracked.contains_key(&ip(4)));
+    }
+
+    #[test]
+    fn take_evicts_the_least_recently_used_at_the_cap() {
+        // Buckets stay partly drained for a minute, so none can be pruned as full
+        let buckets = TokenBuckets::new(budget(1, 5), 4);
+        let start = Instant::now();
+
+        for last in 1..=4 {
+            buckets.take(ip(last), start + Duration::from_secs(last as u64));
+        }
+        buckets.take(ip(5), start + Duration::from_secs(5));
+        // Known clients never trigger an eviction
+        buckets.take(ip(2), start + Duration::from_secs(6));
+
+        let tracked = buckets.buckets.lock().unwrap();
+        assert_eq!(tracked.len(), 4);
+        assert!(!tracked.contains_key(&ip(1)));
+        assert!((2..=5).all(|last| tracked.contains_key(&ip(last))));
+    }
+}
@@ -0,0 +1,574 @@
+use crate::config::RdapConfig;
+use crate::models::{RdapContact, RdapRecord};
+use crate::prefix_tree::{parse_range, PrefixTree};
+use ipnet::IpNet;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Network objects are a few KB, anything far larger is not an RDAP answer
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+const CACHE_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+// IANA bootstrap registry (RFC 9224), each service maps CIDRs to the registry's base URLs
+#[derive(Debug, Deserialize)]
+struct BootstrapFile {
+    services: Vec<(Vec<String>, Vec<String>)>,
+}
+
+// The parts of an RDAP IP network object (RFC 9083) that end up in `RdapRecord`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct IpNetwork {
+    handle: String,
+    name: String,
+    start_address: String,
+    end_address: String,
+    #[serde(rename = "type")]
+    allocation_type: String,
+    country: String,
+    #[serde(rename = "cidr0_cidrs")]
+    cidrs: Vec<Cidr>,
+    events: Vec<Event>,
+    entities: Vec<Entity>,
+}
+
+// RDAP CIDR extension (`cidr0`), carrying either `v4prefix` or `v6prefix`
+#[derive(Debug, Deserialize)]
+struct Cidr {
+    v4prefix: Option<String>,
+    v6prefix: Option<String>,
+    length: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+    event_action: String,
+    event_date: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Entity {
+    handle: String,
+    roles: Vec<String>,
+    // jCard (RFC 7095): ["vcard", [[name, parameters, type, value], ...]]
+    vcard_array: Value,
+    entities: Vec<Entity>,
+}
+
+#[derive(Debug)]
+pub enum RdapError {
+    // No registry covers the address, or the registry has no record for it
+    NotFound,
+    Upstream(String),
+}
+
+// Answers keyed by the network they describe, so one query covers every address in it. Misses
+// are kept for the /24 or /48 around the address
+pub struct RdapCache {
+    ttl: Duration,
+    max_entries: usize,
+    networks: Mutex<PrefixTree<(Instant, Option<RdapRecord>)>>,
+}
+
+impl RdapCache {
+    fn get(&self, ip: IpAddr) -> Option<Option<RdapRecord>> {
+        let networks = self.networks.lock().unwrap();
+        let (_, (fetched, record)) = networks.longest_match(ip)?;
+        (fetched.elapsed() < self.ttl).then(|| record.clone())
+    }
+
+    // Full caches take no new entries until the next prune
+    fn insert(&self, network: IpNet, record: Option<RdapRecord>) {
+        let mut networks = self.networks.lock().unwrap();
+        if networks.len() < self.max_entries {
+            networks.insert(network, (Instant::now(), record));
+        }
+    }
+
+    fn prune(&self) {
+        let mut networks = self.networks.lock().unwrap();
+        networks.retain(|_, (fetched, _)| fetched.elapsed() < self.ttl);
+    }
+}
+
+pub struct RdapClient {
+    pub enabled: bool,
+    registries: PrefixTree<Vec<String>>,
+    base_url: Option<String>,
+    agent: ureq::Agent,
+    cache: Arc<RdapCache>,
+}
+
+impl RdapClient {
+    pub fn new(config: &RdapConfig) -> Result<Self, String> {
+        let mut registries = PrefixTree::default();
+        // A fixed server makes the bootstrap files unnecessary
+        if config.enabled && config.base_url.is_none() {
+            for path in &config.bootstrap_files {
+                let file: BootstrapFile = fs::read_to_string(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|data| serde_json::from_str(&data).map_err(|err| err.to_string()))
+                    .map_err(|err| format!("{}: {}", path, err))?;
+
+                for (networks, urls) in file.services {
+                    for network in networks {
+                        let network = network
+                            .parse::<IpNet>()
+                            .map_err(|_| format!("{}: `{}` is not a network", path, network))?;
+                        registries.insert(network, urls.clone());
+                    }
+                }
+            }
+        }
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .user_agent(concat!("ipeek/", env!("CARGO_PKG_VERSION")))
+            .build();
+
+        Ok(Self {
+            enabled: config.enabled,
+            registries,
+            base_url: config.base_url.clone(),
+            agent,
+            cache: Arc::new(RdapCache {
+                ttl: Duration::from_secs(config.cache_ttl_secs),
+                max_entries: config.cache_max_entries,
+                networks: Mutex::new(PrefixTree::default()),
+            }),
+        })
+    }
+
+    pub fn cache(&self) -> Arc<RdapCache> {
+        self.cache.clone()
+    }
+
+    // Blocking, run it through `web::block`
+    pub fn lookup(&self, ip: IpAddr) -> Result<RdapRecord, RdapError> {
+        if let Some(cached) = self.cache.get(ip) {
+            return cached
+                .map(|record| RdapRecord {
+                    ip: ip.to_string(),
+                    ..record
+                })
+                .ok_or(RdapError::NotFound);
+        }
+
+        let base_url = self.base_url(ip).ok_or(RdapError::NotFound)?;
+        let url = format!("{}/ip/{}", base_url.trim_end_matches('/'), ip);
+
+        let network: IpNetwork = match self
+            .agent
+            .get(&url)
+            .set("Accept", "application/rdap+json")
+            .call()
+        {
+            Ok(response) => {
+                serde_json::from_reader(response.into_reader().take(MAX_RESPONSE_SIZE))
+                    .map_err(|err| RdapError::Upstream(format!("invalid response: {}", err)))?
+            }
+            Err(ureq::Error::Status(404, _)) => {
+                let prefix_len = if ip.is_ipv4() { 24 } else { 48 };
+                if let Ok(network) = IpNet::new(ip, prefix_len) {
+                    self.cache.insert(network.trunc(), None);
+                }
+                return Err(RdapError::NotFound);
+            }
+            Err(ureq::Error::Status(status, _)) => {
+                let message = format!("{} returned HTTP {}", url, status);
+                return Err(RdapError::Upstream(message));
+            }
+            Err(err) => return Err(RdapError::Upstream(err.to_string())),
+        };
+
+        let record = record(ip, network, url);
+        self.cache.insert(cached_network(ip, &record), Some(record.clone()));
+        Ok(record)
+    }
+
+    // Registries list HTTPS and HTTP URLs, HTTPS is preferred
+    fn base_url(&self, ip: IpAddr) -> Option<String> {
+        if let Some(base_url) = &self.base_url {
+            return Some(base_url.clone());
+        }
+        let (_, urls) = self.registries.longest_match(ip)?;
+        urls.iter()
+            .find(|url| url.starts_with("https://"))
+            .or(urls.first())
+            .cloned()
+    }
+}
+
+// Polls the cache and drops expired answers
+pub fn spawn_cache_prune(cache: Arc<RdapCache>) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(CACHE_PRUNE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            cache.prune();
+        }
+    });
+}
+
+// The record's network containing `ip`, answers that don't cover it are only cached for `ip`
+fn cached_network(ip: IpAddr, record: &RdapRecord) -> IpNet {
+    record
+        .network
+        .split(", ")
+        .filter_map(parse_range)
+        .flatten()
+        .find(|network| network.contains(&ip))
+        .unwrap_or_else(|| IpNet::from(ip))
+}
+
+fn record(ip: IpAddr, network: IpNetwork, source: String) -> RdapRecord {
+    let cidrs = network
+        .cidrs
+        .iter()
+        .filter_map(|cidr| {
+            let prefix = cidr.v4prefix.as_ref().or(cidr.v6prefix.as_ref())?;
+            Some(format!("{}/{}", prefix, cidr.length))
+        })
+        .collect::<Vec<String>>();
+    let event_date = |action: &str| {
+        network
+            .events
+            .iter()
+            .find(|event| event.event_action == action)
+            .map(|event| event.event_date.clone())
+            .unwrap_or_default()
+    };
+
+    RdapRecord {
+        ip: ip.to_string(),
+        handle: network.handle.clone(),
+        name: network.name.clone(),
+        network: if cidrs.is_empty() {
+            format!("{} - {}", network.start_address, network.end_address)
+        } else {
+            cidrs.join(", ")
+        },
+        start_address: network.start_address.clone(),
+        end_address: network.end_address.clone(),
+        allocation_type: network.allocation_type.clone(),
+        country: network.country.clone(),
+        registrant: find_contact(&network.entities, "registrant").unwrap_or_default(),
+        abuse_contact: find_contact(&network.entities, "abuse").unwrap_or_default(),
+        registration_date: event_date("registration"),
+        last_changed_date: event_date("last changed"),
+        source,
+    }
+}
+
+// Registries nest contacts differently, e.g. ARIN puts the abuse role below the registrant,
+// so the entities are searched depth first
+fn find_contact(entities: &[Entity], role: &str) -> Option<RdapContact> {
+    entities.iter().find_map(|entity| {
+        if entity.roles.iter().any(|entity_role| entity_role == role) {
+            Some(RdapContact {
+                handle: entity.handle.clone(),
+                name: vcard_property(&entity.vcard_array, "fn"),
+                email: vcard_property(&entity.vcard_array, "email"),
+                phone: vcard_property(&entity.vcard_array, "tel")
+                    .trim_start_matches("tel:")
+                    .to_string(),
+            })
+        } else {
+            find_contact(&entity.entities, role)
+        }
+    })
+}
+
+fn vcard_property(vcard: &Value, name: &str) -> String {
+    vcard
+        .get(1)
+        .and_then(Value::as_array)
+        .and_then(|properties| {
+            properties
+                .iter()
+                .find(|property| property.get(0).and_then(Value::as_str) == Some(name))
+        })
+        .and_then(|property| property.get(3))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Modelled on ARIN's answer for 8.8.8.8, abridged to the members ipeek reads plus a few it
+    // ignores
+    const ARIN_RESPONSE: &str = r#"{
+  "rdapConformance": ["nro_rdap_profile_0", "rdap_level_0", "cidr0", "arin_originas0"],
+  "objectClassName": "ip network",
+  "handle": "NET-8-8-8-0-2",
+  "startAddress": "8.8.8.0",
+  "endAddress": "8.8.8.255",
+  "ipVersion": "v4",
+  "name": "GOGL",
+  "type": "DIRECT ALLOCATION",
+  "parentHandle": "NET-8-0-0-0-0",
+  "cidr0_cidrs": [{"v4prefix": "8.8.8.0", "length": 24}],
+  "events": [
+    {"eventAction": "last changed", "eventDate": "2023-12-28T17:24:56-05:00"},
+    {"eventAction": "registration", "eventDate": "2023-12-28T17:24:33-05:00"}
+  ],
+  "entities": [
+    {
+      "objectClassName": "entity",
+      "handle": "GOGL",
+      "roles": ["registrant"],
+      "vcardArray": ["vcard", [
+        ["version", {}, "text", "4.0"],
+        ["fn", {}, "text", "Google LLC"],
+        ["adr", {"label": "1600 Amphitheatre Parkway\nMountain View\nCA\n94043\nUnited States"},
+          "text", ["", "", "", "", "", "", ""]],
+        ["kind", {}, "text", "org"]
+      ]],
+      "entities": [
+        {
+          "objectClassName": "entity",
+          "handle": "ABUSE5250-ARIN",
+          "roles": ["abuse"],
+          "vcardArray": ["vcard", [
+            ["version", {}, "text", "4.0"],
+            ["fn", {}, "text", "Abuse"],
+            ["kind", {}, "text", "group"],
+            ["email", {}, "text", "network-abuse@google.com"],
+            ["tel", {"type": ["work", "voice"]}, "text", "+1-650-253-0000"]
+          ]]
+        }
+      ]
+    }
+  ],
+  "port43": "whois.arin.net",
+  "status": ["active"]
+}"#;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn arin_record(address: &str) -> RdapRecord {
+        let network = serde_json::from_str(ARIN_RESPONSE).unwrap();
+        record(
+            ip(address),
+            network,
+            "https://rdap.arin.net/registry/ip/8.8.8.8".to_string(),
+        )
+    }
+
+    // Answers every request with `status` and `body`, counting the requests
+    fn serve(status: &'static str, body: &'static str) -> (RdapClient, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/rdap+json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let client = RdapClient::new(&RdapConfig {
+            enabled: true,
+            base_url: Some(base_url),
+            ..RdapConfig::default()
+        })
+        .unwrap();
+        (client, requests)
+    }
+
+    #[test]
+    fn record_from_an_arin_response() {
+        let record = arin_record("8.8.8.8");
+        assert_eq!(record.ip, "8.8.8.8");
+        assert_eq!(
+            (record.handle.as_str(), record.name.as_str()),
+            ("NET-8-8-8-0-2", "GOGL")
+        );
+        assert_eq!(record.network, "8.8.8.0/24");
+        assert_eq!(
+            (record.start_address.as_str(), record.end_address.as_str()),
+            ("8.8.8.0", "8.8.8.255")
+        );
+        assert_eq!(record.allocation_type, "DIRECT ALLOCATION");
+        assert_eq!(record.registration_date, "2023-12-28T17:24:33-05:00");
+        assert_eq!(record.last_changed_date, "2023-12-28T17:24:56-05:00");
+        assert_eq!(record.source, "https://rdap.arin.net/registry/ip/8.8.8.8");
+    }
+
+    #[test]
+    fn record_without_cidrs_uses_the_address_range() {
+        let network = serde_json::from_str(
+            r#"{"startAddress": "192.0.2.0", "endAddress": "192.0.3.127", "name": "EXAMPLE"}"#,
+        )
+        .unwrap();
+        let record = record(ip("192.0.2.1"), network, String::new());
+        assert_eq!(record.network, "192.0.2.0 - 192.0.3.127");
+        assert!(record.abuse_contact.email.is_empty());
+    }
+
+    #[test]
+    fn find_contact_searches_nested_entities() {
+        let network: IpNetwork = serde_json::from_str(ARIN_RESPONSE).unwrap();
+
+        let registrant = find_contact(&network.entities, "registrant").unwrap();
+        assert_eq!(
+            (registrant.handle.as_str(), registrant.name.as_str()),
+            ("GOGL", "Google LLC")
+        );
+        assert!(registrant.email.is_empty());
+
+        // ARIN nests the abuse role below the registrant
+        let abuse = find_contact(&network.entities, "abuse").unwrap();
+        assert_eq!(abuse.handle, "ABUSE5250-ARIN");
+        assert_eq!(abuse.name, "Abuse");
+        assert_eq!(abuse.email, "network-abuse@google.com");
+        assert_eq!(abuse.phone, "+1-650-253-0000");
+
+        assert!(find_contact(&network.entities, "technical").is_none());
+    }
+
+    #[test]
+    fn vcard_property_strips_tel_uris_and_ignores_garbage() {
+        let entity: Entity = serde_json::from_str(
+            r#"{"handle": "AR1-RIPE", "roles": ["abuse"], "vcardArray": ["vcard", [
+                ["fn", {}, "text", "Abuse Team"],
+                ["tel", {"type": "voice"}, "uri", "tel:+31205354444"]
+            ]]}"#,
+        )
+        .unwrap();
+        let contact = find_contact(&[entity], "abuse").unwrap();
+        assert_eq!(
+            (contact.name.as_str(), contact.phone.as_str()),
+            ("Abuse Team", "+31205354444")
+        );
+
+        assert_eq!(vcard_property(&Value::Null, "fn"), "");
+        assert_eq!(
+            vcard_property(&serde_json::json!(["vcard", [["fn", {}]]]), "fn"),
+            ""
+        );
+    }
+
+    #[test]
+    fn cached_network_is_the_record_network_containing_the_address() {
+        assert_eq!(
+            cached_network(ip("8.8.8.8"), &arin_record("8.8.8.8")).to_string(),
+            "8.8.8.0/24"
+        );
+
+        let mut record = arin_record("8.8.8.8");
+        record.network = "192.0.2.0/24, 198.51.100.0/24".to_string();
+        assert_eq!(
+            cached_network(ip("198.51.100.7"), &record).to_string(),
+            "198.51.100.0/24"
+        );
+
+        record.network = "192.0.2.0 - 192.0.3.127".to_string();
+        assert_eq!(
+            cached_network(ip("192.0.3.1"), &record).to_string(),
+            "192.0.3.0/25"
+        );
+
+        // Answers for a network that doesn't contain the address only cover the address
+        assert_eq!(
+            cached_network(ip("203.0.113.1"), &record).to_string(),
+            "203.0.113.1/32"
+        );
+    }
+
+    #[test]
+    fn lookup_answers_the_whole_network_from_the_cache() {
+        let (client, requests) = serve("200 OK", ARIN_RESPONSE);
+
+        let record = client.lookup(ip("8.8.8.8")).unwrap();
+        assert_eq!(record.abuse_contact.email, "network-abuse@google.com");
+        let record = client.lookup(ip("8.8.8.4")).unwrap();
+        assert_eq!(
+            (record.ip.as_str(), record.network.as_str()),
+            ("8.8.8.4", "8.8.8.0/24")
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        client.lookup(ip("8.8.4.4")).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn lookup_caches_misses_for_the_surrounding_prefix() {
+        let (client, requests) = serve("404 Not Found", "");
+
+        assert!(matches!(
+            client.lookup(ip("192.0.2.1")),
+            Err(RdapError::NotFound)
+        ));
+        assert!(matches!(
+            client.lookup(ip("192.0.2.254")),
+            Err(RdapError::NotFound)
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(matches!(
+            client.lookup(ip("192.0.3.1")),
+            Err(RdapError::NotFound)
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        assert!(matches!(
+            client.lookup(ip("2001:db8:1:ffff::1")),
+            Err(RdapError::NotFound)
+        ));
+        assert!(matches!(
+            client.lookup(ip("2001:db8:1::1")),
+            Err(RdapError::NotFound)
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert!(matches!(
+            client.lookup(ip("2001:db8:2::1")),
+            Err(RdapError::NotFound)
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn lookup_does_not_cache_upstream_errors() {
+        let (client, requests) = serve("503 Service Unavailable", "");
+        assert!(matches!(
+            client.lookup(ip("192.0.2.1")),
+            Err(RdapError::Upstream(_))
+        ));
+        assert!(matches!(
+            client.lookup(ip("192.0.2.1")),
+            Err(RdapError::Upstream(_))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::models::AddressClassification;
+use crate::prefix_tree::PrefixTree;
+use ipnet::IpNet;
+use lazy_static::lazy_static;
+use std::net::IpAddr;
+
+struct SpecialRange {
+    network: &'static str,
+    name: &'static str,
+    rfc: &'static str,
+    // "Globally Reachable" and "Reserved-by-Protocol" columns of the IANA registries
+    global: bool,
+    reserved: bool,
+}
+
+const fn range(
+    network: &'static str,
+    name: &'static str,
+    rfc: &'static str,
+    global: bool,
+    reserved: bool,
+) -> SpecialRange {
+    SpecialRange {
+        network,
+        name,
+        rfc,
+        global,
+        reserved,
+    }
+}
+
+// IANA IPv4 and IPv6 Special-Purpose Address Registries (RFC 6890) plus the multicast blocks.
+// "N/A" reachability is treated as global for the transition prefixes clients actually use.
+// IPv4-mapped IPv6 addresses are classified as the IPv4 address they carry
+#[rustfmt::skip]
+const SPECIAL_RANGES: &[SpecialRange] = &[
+    range("0.0.0.0/8", "This network", "RFC 791", false, true),
+    range("0.0.0.0/32", "This host on this network", "RFC 1122", false, true),
+    range("10.0.0.0/8", "Private-Use", "RFC 1918", false, false),
+    range("100.64.0.0/10", "Shared Address Space", "RFC 6598", false, false),
+    range("127.0.0.0/8", "Loopback", "RFC 1122", false, true),
+    range("169.254.0.0/16", "Link Local", "RFC 3927", false, true),
+    range("172.16.0.0/12", "Private-Use", "RFC 1918", false, false),
+    range("192.0.0.0/24", "IETF Protocol Assignments", "RFC 6890", false, false),
+    range("192.0.0.0/29", "IPv4 Service Continuity Prefix", "RFC 7335", false, false),
+    range("192.0.0.8/32", "IPv4 dummy address", "RFC 7600", false, false),
+    range("192.0.0.9/32", "Port Control Protocol Anycast", "RFC 7723", true, false),
+    range("192.0.0.10/32", "TURN Anycast", "RFC 8155", true, false),
+    range("192.0.0.170/31", "NAT64/DNS64 Discovery", "RFC 7050", false, true),
+    range("192.0.2.0/24", "Documentation (TEST-NET-1)", "RFC 5737", false, false),
+    range("192.31.196.0/24", "AS112-v4", "RFC 7535", true, false),
+    range("192.52.193.0/24", "AMT", "RFC 7450", true, false),
+    range("192.88.99.0/24", "Deprecated (6to4 Relay Anycast)", "RFC 7526", false, false),
+    range("192.168.0.0/16", "Private-Use", "RFC 1918", false, false),
+    range("192.175.48.0/24", "Direct Delegation AS112 Service", "RFC 7534", true, false),
+    range("198.18.0.0/15", "Benchmarking", "RFC 2544", false, false),
+    range("198.51.100.0/24", "Documentation (TEST-NET-2)", "RFC 5737", false, false),
+    range("203.0.113.0/24", "Documentation (TEST-NET-3)", "RFC 5737", false, false),
+    range("224.0.0.0/4", "Multicast", "RFC 5771", false, false),
+    range("240.0.0.0/4", "Reserved", "RFC 1112", false, true),
+    range("255.255.255.255/32", "Limited Broadcast", "RFC 919", false, true),
+    range("::/128", "Unspecified Address", "RFC 4291", false, true),
+    range("::1/128", "Loopback Address", "RFC 4291", false, true),
+    range("64:ff9b::/96", "IPv4-IPv6 Translation", "RFC 6052", true, false),
+    range("64:ff9b:1::/48", "IPv4-IPv6 Translation", "RFC 8215", false, false),
+    range("100::/64", "Discard-Only Address Block", "RFC 6666", false, false),
+    range("2001::/23", "IETF Protocol Assignments", "RFC 2928", false, false),
+    range("2001::/32", "TEREDO", "RFC 4380", true, false),
+    range("2001:1::1/128", "Port Control Protocol Anycast", "RFC 7723", true, false),
+    range("2001:1::2/128", "TURN Anycast", "RFC 8155", true, false),
+    range("2001:2::/48", "Benchmarking", "RFC 5180", false, false),
+    range("2001:3::/32", "AMT", "RFC 7450", true, false),
+    range("2001:4:112::/48", "AS112-v6", "RFC 7535", true, false),
+    range("2001:10::/28", "Deprecated (previously ORCHID)", "RFC 4843", false, false),
+    range("2001:20::/28", "ORCHIDv2", "RFC 7343", true, false),
+    range("2001:30::/28", "ORCHIDv2 (Drone Remote ID Entity Tags)", "RFC 9374", true, false),
+    range("2001:db8::/32", "Documentation", "RFC 3849", false, false),
+    range("2002::/16", "6to4", "RFC 3056", true, false),
+    range("2620:4f:8000::/48", "Direct Delegation AS112 Service", "RFC 7534", true, false),
+    range("3fff::/20", "Documentation", "RFC 9637", false, false),
+    range("5f00::/16", "Segment Routing (SRv6) SIDs", "RFC 9602", false, false),
+    range("fc00::/7", "Unique-Local", "RFC 4193", false, false),
+    range("fe80::/10", "Link-Local Unicast", "RFC 4291", false, true),
+    range("ff00::/8", "Multicast", "RFC 4291", false, false),
+];
+
+lazy_static! {
+    static ref SPECIAL_RANGE_TREE: PrefixTree<&'static SpecialRange> = {
+        let mut tree = PrefixTree::default();
+        for special_range in SPECIAL_RANGES {
+            tree.insert(
+                special_range.network.parse::<IpNet>().unwrap(),
+                special_range,
+            );
+        }
+        tree
+    };
+}
+
+pub fn classify(ip: IpAddr) -> AddressClassification {
+    let Some((network, special_range)) = SPECIAL_RANGE_TREE.longest_match(ip) else {
+        return AddressClassification {
+            ip: ip.to_string(),
+            special: false,
+            global: true,
+            ..Default::default()
+        };
+    };
+
+    AddressClassification {
+        ip: ip.to_string(),
+        special: true,
+        range: special_range.name.to_string(),
+        network: network.to_string(),
+        rfc: special_range.rfc.to_string(),
+        global: special_range.global,
+        reserved: special_range.reserved,
+        private: matches!(special_range.name, "Private-Use" | "Unique-Local"),
+    }
+}
+
+// Whether DNSBL and PTR lookups for `ip` can return anything meaningful
+pub fn is_global(ip: IpAddr) -> bool {
+    SPECIAL_RANGE_TREE
+        .longest_match(ip)
+        .is_none_or(|(_, special_range)| special_range.global)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Addresses at and just outside the edges of the blocks, with the network they're classified
+    // by (empty when none) and whether they're global
+    #[rustfmt::skip]
+    const BOUNDARIES: &[(&str, &str, bool)] = &[
+        ("0.0.0.0", "0.0.0.0/32", false),
+        ("0.0.0.1", "0.0.0.0/8", false),
+        ("0.255.255.255", "0.0.0.0/8", false),
+        ("1.0.0.0", "", true),
+        ("9.255.255.255", "", true),
+        ("10.0.0.0", "10.0.0.0/8", false),
+        ("10.255.255.255", "10.0.0.0/8", false),
+        ("11.0.0.0", "", true),
+        ("100.63.255.255", "", true),
+        ("100.64.0.0", "100.64.0.0/10", false),
+        ("100.127.255.255", "100.64.0.0/10", false),
+        ("100.128.0.0", "", true),
+        ("126.255.255.255", "", true),
+        ("127.0.0.0", "127.0.0.0/8", false),
+        ("127.255.255.255", "127.0.0.0/8", false),
+        ("128.0.0.0", "", true),
+        ("169.254.0.0", "169.254.0.0/16", false),
+        ("169.254.255.255", "169.254.0.0/16", false),
+        ("172.15.255.255", "", true),
+        ("172.16.0.0", "172.16.0.0/12", false),
+        ("172.31.255.255", "172.16.0.0/12", false),
+        ("172.32.0.0", "", true),
+        ("192.0.0.0", "192.0.0.0/29", false),
+        ("192.0.0.7", "192.0.0.0/29", false),
+        ("192.0.0.8", "192.0.0.8/32", false),
+        ("192.0.0.9", "192.0.0.9/32", true),
+        ("192.0.0.10", "192.0.0.10/32", true),
+        ("192.0.0.11", "192.0.0.0/24", false),
+        ("192.0.0.170", "192.0.0.170/31", false),
+        ("192.0.0.171", "192.0.0.170/31", false),
+        ("192.0.0.255", "192.0.0.0/24", false),
+        ("192.0.1.0", "", true),
+        ("192.0.2.0", "192.0.2.0/24", false),
+        ("192.0.2.255", "192.0.2.0/24", false),
+        ("192.31.196.0", "192.31.196.0/24", true),
+        ("192.88.99.255", "192.88.99.0/24", false),
+        ("192.167.255.255", "", true),
+        ("192.168.0.0", "192.168.0.0/16", false),
+        ("192.168.255.255", "192.168.0.0/16", false),
+        ("192.169.0.0", "", true),
+        ("198.17.255.255", "", true),
+        ("198.18.0.0", "198.18.0.0/15", false),
+        ("198.19.255.255", "198.18.0.0/15", false),
+        ("198.20.0.0", "", true),
+        ("198.51.100.0", "198.51.100.0/24", false),
+        ("203.0.113.255", "203.0.113.0/24", false),
+        ("223.255.255.255", "", true),
+        ("224.0.0.0", "224.0.0.0/4", false),
+        ("239.255.255.255", "224.0.0.0/4", false),
+        ("240.0.0.0", "240.0.0.0/4", false),
+        ("255.255.255.254", "240.0.0.0/4", false),
+        ("255.255.255.255", "255.255.255.255/32", false),
+        ("::", "::/128", false),
+        ("::1", "::1/128", false),
+        ("::2", "", true),
+        ("::ffff:10.0.0.1", "10.0.0.0/8", false),
+        ("::ffff:8.8.8.8", "", true),
+        ("64:ff9b::", "64:ff9b::/96", true),
+        ("64:ff9b::ffff:ffff", "64:ff9b::/96", true),
+        ("64:ff9b:1::", "64:ff9b:1::/48", false),
+        ("100::", "100::/64", false),
+        ("100::ffff:ffff:ffff:ffff", "100::/64", false),
+        ("100:0:0:1::", "", true),
+        ("2000:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "", true),
+        ("2001::", "2001::/32", true),
+        ("2001:0:ffff:ffff:ffff:ffff:ffff:ffff", "2001::/32", true),
+        ("2001:1::", "2001::/23", false),
+        ("2001:1::1", "2001:1::1/128", true),
+        ("2001:1::2", "2001:1::2/128", true),
+        ("2001:1::3", "2001::/23", false),
+        ("2001:2::", "2001:2::/48", false),
+        ("2001:3::", "2001:3::/32", true),
+        ("2001:10::", "2001:10::/28", false),
+        ("2001:1f:ffff:ffff:ffff:ffff:ffff:ffff", "2001:10::/28", false),
+        ("2001:20::", "2001:20::/28", true),
+        ("2001:2f:ffff:ffff:ffff:ffff:ffff:ffff", "2001:20::/28", true),
+        ("2001:30::", "2001:30::/28", true),
+        ("2001:3f:ffff:ffff:ffff:ffff:ffff:ffff", "2001:30::/28", true),
+        ("2001:40::", "2001::/23", false),
+        ("2001:1ff:ffff:ffff:ffff:ffff:ffff:ffff", "2001::/23", false),
+        ("2001:200::", "", true),
+        ("2001:db7:ffff:ffff:ffff:ffff:ffff:ffff", "", true),
+        ("2001:db8::", "2001:db8::/32", false),
+        ("2001:db8:ffff:ffff:ffff:ffff:ffff:ffff", "2001:db8::/32", false),
+        ("2001:db9::", "", true),
+        ("2002::", "2002::/16", true),
+        ("3ffe:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "", true),
+        ("3fff::", "3fff::/20", false),
+        ("3fff:fff:ffff:ffff:ffff:ffff:ffff:ffff", "3fff::/20", false),
+        ("3fff:1000::", "", true),
+        ("5f00::", "5f00::/16", false),
+        ("fbff:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "", true),
+        ("fc00::", "fc00::/7", false),
+        ("fdff:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "fc00::/7", false),
+        ("fe00::", "", true),
+        ("fe80::", "fe80::/10", false),
+        ("febf:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "fe80::/10", false),
+        ("fec0::", "", true),
+        ("ff00::", "ff00::/8", false),
+        ("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "ff00::/8", false),
+    ];
+
+    #[test]
+    fn classify_block_boundaries() {
+        for &(address, network, global) in BOUNDARIES {
+            let ip = address.parse().unwrap();
+            let classification = classify(ip);
+            assert_eq!(classification.network, network, "{}", address);
+            assert_eq!(classification.special, !network.is_empty(), "{}", address);
+            assert_eq!(classification.global, global, "{}", address);
+            assert_eq!(is_global(ip), global, "{}", address);
+        }
+    }
+
+    #[test]
+    fn classify_covers_every_block_from_first_to_last_address() {
+        for special_range in SPECIAL_RANGES {
+            let network = special_range.network.parse::<IpNet>().unwrap();
+            for ip in [network.network(), network.broadcast()] {
+                let classification = classify(ip);
+                let matched = classification.network.parse::<IpNet>().unwrap();
+                // A more specific block may take over the edges, e.g. 0.0.0.0/32 in 0.0.0.0/8
+                assert!(network.contains(&matched), "{} in {}", ip, network);
+                if matched == network {
+                    assert_eq!(classification.range, special_range.name, "{}", ip);
+                    assert_eq!(classification.global, special_range.global, "{}", ip);
+                    assert_eq!(classification.reserved, special_range.reserved, "{}", ip);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn classify_flags_private_ranges() {
+        for (address, private) in [("10.1.2.3", true), ("fd00::1", true), ("100.64.0.1", false)] {
+            assert_eq!(
+                classify(address.parse().unwrap()).private,
+                private,
+                "{}",
+                address
+            );
+        }
+    }
+}
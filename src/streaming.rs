@@ -0,0 +1,31 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+// Response body fed chunk by chunk from a channel, the response ends once every sender is dropped
+pub struct ChannelBody {
+    rx: mpsc::UnboundedReceiver<Bytes>,
+}
+
+pub fn channel() -> (mpsc::UnboundedSender<Bytes>, ChannelBody) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (tx, ChannelBody { rx })
+}
+
+impl MessageBody for ChannelBody {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        self.rx.poll_recv(cx).map(|chunk| chunk.map(Ok))
+    }
+}
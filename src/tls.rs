@@ -0,0 +1,150 @@
+use crate::config::TlsConfig;
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use rustls::crypto::ring::{default_provider, sign::any_supported_type};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::any::Any;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+// TLS details of a connection terminated by ipeek itself, stored in the connection data
+#[derive(Clone)]
+pub struct TlsConnectionInfo {
+    pub version: String,
+    pub cipher_suite: String,
+    pub alpn: String,
+    pub sni: String,
+}
+
+// Serves the current certificate and swaps it whenever the files on disk change
+#[derive(Debug)]
+pub struct CertificateResolver {
+    cert_path: String,
+    key_path: String,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateResolver {
+    fn load(cert_path: &str, key_path: &str) -> Result<Self, String> {
+        Ok(Self {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            current: RwLock::new(Arc::new(load_certified_key(cert_path, key_path)?)),
+        })
+    }
+
+    fn reload(&self) -> Result<(), String> {
+        let certified_key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = Arc::new(certified_key);
+        Ok(())
+    }
+
+    // Latest modification time of the certificate and key, `None` while one of them is missing
+    fn modified(&self) -> Option<SystemTime> {
+        let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Some(modified(&self.cert_path)?.max(modified(&self.key_path)?))
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+pub fn server_config(
+    config: &TlsConfig,
+) -> Result<(ServerConfig, Arc<CertificateResolver>), String> {
+    let resolver = Arc::new(CertificateResolver::load(
+        &config.cert_path,
+        &config.key_path,
+    )?);
+
+    // actix advertises `h2` and `http/1.1` via ALPN on top of this config
+    let server_config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| err.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver.clone());
+
+    Ok((server_config, resolver))
+}
+
+// Polls the certificate files and reloads them when they change, a broken update keeps the
+// previous certificate in place
+pub fn spawn_reload(resolver: Arc<CertificateResolver>, interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut last_modified = resolver.modified();
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            let modified = resolver.modified();
+            if modified.is_none() || modified == last_modified {
+                continue;
+            }
+
+            match resolver.reload() {
+                Ok(()) => {
+                    log::info!("Reloaded TLS certificate from {}", resolver.cert_path);
+                    last_modified = modified;
+                }
+                Err(err) => log::warn!("Failed to reload TLS certificate: {}", err),
+            }
+        }
+    });
+}
+
+// `HttpServer::on_connect` callback exposing the TLS session details to handlers
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+
+    data.insert(TlsConnectionInfo {
+        version: session
+            .protocol_version()
+            .and_then(|version| version.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        cipher_suite: session
+            .negotiated_cipher_suite()
+            .and_then(|suite| suite.suite().as_str())
+            .unwrap_or_default()
+            .to_string(),
+        alpn: session
+            .alpn_protocol()
+            .map(|protocol| String::from_utf8_lossy(protocol).to_string())
+            .unwrap_or_default(),
+        sni: session.server_name().unwrap_or_default().to_string(),
+    });
+}
+
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, String> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|err| format!("{}: {}", path, err))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("{}: {}", cert_path, err))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", cert_path));
+    }
+
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .map_err(|err| format!("{}: {}", key_path, err))?
+        .ok_or_else(|| format!("{}: no private key found", key_path))?;
+    let signing_key = any_supported_type(&key).map_err(|err| format!("{}: {}", key_path, err))?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
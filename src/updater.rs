@@ -0,0 +1,241 @@
+use crate::config::UpdaterConfig;
+use crate::AppState;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use maxminddb::Reader;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Archives larger than this are rejected, the City edition is around 40 MB
+const MAX_ARCHIVE_SIZE: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum UpdateOutcome {
+    Updated,
+    UpToDate,
+}
+
+// One database file and the edition it is downloaded from
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub edition: String,
+    pub path: String,
+}
+
+// Downloads tar.gz archives, verifies them against their published SHA256 and installs the
+// contained `.mmdb` atomically next to the configured path. Readers pick the new file up with
+// `SharedReader::reload_if_changed`
+pub struct Updater {
+    config: UpdaterConfig,
+    targets: Vec<Target>,
+    agent: ureq::Agent,
+}
+
+impl Updater {
+    pub fn new(config: &UpdaterConfig, geo_db_path: &str, asn_db_path: &str) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(10))
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(concat!("ipeek/", env!("CARGO_PKG_VERSION")))
+            .build();
+
+        Self {
+            targets: vec![
+                Target {
+                    edition: config.geo_edition.clone(),
+                    path: geo_db_path.to_string(),
+                },
+                Target {
+                    edition: config.asn_edition.clone(),
+                    path: asn_db_path.to_string(),
+                },
+            ],
+            config: config.clone(),
+            agent,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval_hours * 60 * 60)
+    }
+
+    // Updates every database, a failure of one doesn't stop the others
+    pub fn run(&self) -> Vec<(Target, Result<UpdateOutcome, String>)> {
+        self.targets
+            .iter()
+            .map(|target| (target.clone(), self.update(target)))
+            .collect()
+    }
+
+    fn update(&self, target: &Target) -> Result<UpdateOutcome, String> {
+        let expected = self.fetch_checksum(&target.edition)?;
+        let checksum_path = format!("{}.sha256", target.path);
+        let installed = fs::read_to_string(&checksum_path).unwrap_or_default();
+        if installed.trim() == expected && Path::new(&target.path).exists() {
+            return Ok(UpdateOutcome::UpToDate);
+        }
+
+        let archive = self.fetch_archive(&target.edition)?;
+        let actual = hex(&Sha256::digest(&archive));
+        if actual != expected {
+            return Err(format!(
+                "checksum mismatch, expected {expected} but got {actual}"
+            ));
+        }
+
+        let temp_path = format!("{}.tmp", target.path);
+        let result = extract(&archive, &target.edition, &temp_path)
+            .and_then(|_| {
+                Reader::open_readfile(&temp_path)
+                    .map(|_| ())
+                    .map_err(|err| format!("downloaded database is invalid: {err}"))
+            })
+            .and_then(|_| {
+                fs::rename(&temp_path, &target.path)
+                    .map_err(|err| format!("failed to install {}: {err}", target.path))
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result?;
+
+        fs::write(&checksum_path, format!("{expected}\n"))
+            .map_err(|err| format!("failed to write {checksum_path}: {err}"))?;
+        Ok(UpdateOutcome::Updated)
+    }
+
+    fn fetch_checksum(&self, edition: &str) -> Result<String, String> {
+        let body = self
+            .get(edition, "tar.gz.sha256")?
+            .into_string()
+            .map_err(|err| format!("failed to read checksum: {err}"))?;
+
+        // `sha256sum` format, the digest followed by the file name
+        let checksum = body
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("checksum file doesn't contain a SHA256 digest".to_string());
+        }
+        Ok(checksum)
+    }
+
+    fn fetch_archive(&self, edition: &str) -> Result<Vec<u8>, String> {
+        let mut archive = Vec::new();
+        self.get(edition, "tar.gz")?
+            .into_reader()
+            .take(MAX_ARCHIVE_SIZE + 1)
+            .read_to_end(&mut archive)
+            .map_err(|err| format!("failed to download archive: {err}"))?;
+        if archive.len() as u64 > MAX_ARCHIVE_SIZE {
+            return Err("archive exceeds the maximum size".to_string());
+        }
+        Ok(archive)
+    }
+
+    fn get(&self, edition: &str, suffix: &str) -> Result<ureq::Response, String> {
+        let url = self
+            .config
+            .url
+            .replace("{edition}", edition)
+            .replace("{suffix}", suffix);
+        let mut request = self.agent.get(&url);
+        if let (Some(account_id), Some(license_key)) =
+            (&self.config.account_id, &self.config.license_key)
+        {
+            let credentials = base64::engine::general_purpose::STANDARD
+                .encode(format!("{account_id}:{license_key}"));
+            request = request.set("Authorization", &format!("Basic {credentials}"));
+        }
+
+        request.call().map_err(|err| match err {
+            ureq::Error::Status(status, _) => format!("{url} returned HTTP {status}"),
+            err => format!("request to {url} failed: {err}"),
+        })
+    }
+}
+
+// Writes the `<edition>.mmdb` entry of the archive to `destination`
+fn extract(archive: &[u8], edition: &str, destination: &str) -> Result<(), String> {
+    let file_name = format!("{edition}.mmdb");
+    let mut entries = tar::Archive::new(GzDecoder::new(archive));
+    let entries = entries
+        .entries()
+        .map_err(|err| format!("failed to read archive: {err}"))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|err| format!("failed to read archive: {err}"))?;
+        let matches = entry
+            .path()
+            .map(|path| {
+                path.file_name()
+                    .is_some_and(|name| name == file_name.as_str())
+            })
+            .unwrap_or(false);
+        if !matches {
+            continue;
+        }
+
+        let mut file = fs::File::create(destination)
+            .map_err(|err| format!("failed to create {destination}: {err}"))?;
+        io::copy(&mut entry, &mut file)
+            .map_err(|err| format!("failed to extract {file_name}: {err}"))?;
+        return Ok(());
+    }
+
+    Err(format!("archive doesn't contain {file_name}"))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// Runs the updater every `interval_hours`, starting right away, and swaps the readers in
+// `state` once a new file is installed. Files installed by `ipeek update-db` are picked up too
+pub fn spawn<S>(updater: Updater, state: Arc<AppState<S>>)
+where
+    S: AsRef<[u8]> + Send + Sync + 'static,
+{
+    let updater = Arc::new(updater);
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(updater.interval());
+
+        loop {
+            ticker.tick().await;
+
+            let task_updater = updater.clone();
+            match tokio::task::spawn_blocking(move || task_updater.run()).await {
+                Ok(results) => log_results(&results),
+                Err(err) => log::warn!("Database update task failed: {}", err),
+            }
+
+            for reader in [&state.geo_db, &state.asn_db] {
+                match reader.reload_if_changed() {
+                    Ok(true) => {
+                        log::info!("Reloaded {} database from {}", reader.name, reader.path)
+                    }
+                    Ok(false) => {}
+                    Err(err) => log::warn!("Failed to reload {} database: {}", reader.name, err),
+                }
+            }
+        }
+    });
+}
+
+fn log_results(results: &[(Target, Result<UpdateOutcome, String>)]) {
+    for (target, result) in results {
+        match result {
+            Ok(UpdateOutcome::Updated) => {
+                log::info!("Downloaded {} to {}", target.edition, target.path)
+            }
+            Ok(UpdateOutcome::UpToDate) => log::debug!("{} is up to date", target.edition),
+            Err(err) => log::warn!("Failed to update {}: {}", target.edition, err),
+        }
+    }
+}
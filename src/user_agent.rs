@@ -0,0 +1,176 @@
+use crate::models::UserAgentRecord;
+use actix_web::HttpRequest;
+use lazy_static::lazy_static;
+use woothee::parser::Parser;
+
+const UNKNOWN: &str = "UNKNOWN";
+
+// Desktop API clients woothee doesn't know, matched anywhere in the lowercase User-Agent since
+// not all of them put their own name first
+static API_CLIENTS: [(&str, &str); 9] = [
+    ("postmanruntime/", "Postman"),
+    ("httpie/", "HTTPie"),
+    ("insomnia/", "Insomnia"),
+    ("swagger", "Swagger"),
+    ("apifox/", "Apifox"),
+    ("soapui", "SoapUI"),
+    ("paw/", "Paw"),
+    ("vscode-restclient", "REST Client"),
+    ("rest-client", "rest-client"),
+];
+
+lazy_static! {
+    static ref PARSER: Parser = Parser::new();
+}
+
+impl UserAgentRecord {
+    // Browsers get the HTML-ish documentation on `/` instead of the bare IP
+    pub fn is_browser(&self) -> bool {
+        matches!(self.device.as_str(), "desktop" | "mobile" | "appliance") && !self.bot
+    }
+
+    pub fn is_api_client(&self) -> bool {
+        self.device == "api-client"
+    }
+}
+
+pub fn request_user_agent(req: &HttpRequest) -> UserAgentRecord {
+    let user_agent = req
+        .headers()
+        .get("User-Agent")
+        .and_then(|ua| ua.to_str().ok())
+        .unwrap_or_default();
+    parse(user_agent)
+}
+
+pub fn parse(user_agent: &str) -> UserAgentRecord {
+    let lower = user_agent.to_ascii_lowercase();
+    if let Some((pattern, name)) = API_CLIENTS
+        .iter()
+        .find(|(pattern, _)| lower.contains(pattern))
+    {
+        return UserAgentRecord {
+            user_agent: user_agent.to_string(),
+            browser: name.to_string(),
+            browser_version: product_version(user_agent, pattern),
+            device: "api-client".to_string(),
+            ..UserAgentRecord::default()
+        };
+    }
+
+    let result = match PARSER.parse(user_agent) {
+        Some(result) => result,
+        None => {
+            return UserAgentRecord {
+                user_agent: user_agent.to_string(),
+                device: "unknown".to_string(),
+                ..UserAgentRecord::default()
+            }
+        }
+    };
+
+    let known = |value: &str| {
+        if value == UNKNOWN {
+            String::new()
+        } else {
+            value.to_string()
+        }
+    };
+
+    // woothee reports HTTP libraries (curl, wget, ...) by putting the library into `version`
+    let (browser, browser_version, device) = match (result.category, result.browser_type) {
+        (_, "crawler") | ("crawler", _) => (known(result.name), known(result.version), "bot"),
+        ("misc", _) if result.name == "HTTP Library" => (
+            result.version.to_string(),
+            product_version(user_agent, &result.version.to_ascii_lowercase()),
+            "cli",
+        ),
+        ("pc", _) => (known(result.name), known(result.version), "desktop"),
+        ("smartphone", _) | ("mobilephone", _) => {
+            (known(result.name), known(result.version), "mobile")
+        }
+        ("appliance", _) => (known(result.name), known(result.version), "appliance"),
+        _ => (known(result.name), known(result.version), "unknown"),
+    };
+
+    UserAgentRecord {
+        user_agent: user_agent.to_string(),
+        browser,
+        browser_version,
+        os: known(result.os),
+        os_version: known(&result.os_version),
+        device: device.to_string(),
+        bot: device == "bot",
+    }
+}
+
+// Version of the first `product/version` token whose lowercase product contains `product`,
+// e.g. `2023.5.8` for `insomnia` in `Mozilla/5.0 ... insomnia/2023.5.8`
+fn product_version(user_agent: &str, product: &str) -> String {
+    let product = product.trim_end_matches('/');
+    user_agent
+        .split_whitespace()
+        .map(|token| token.trim_matches(|c| matches!(c, '(' | ')' | ';' | ',')))
+        .filter_map(|token| token.split_once('/'))
+        .find(|(name, _)| name.to_ascii_lowercase().contains(product))
+        .map(|(_, version)| version.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_parsed(user_agent: &str, browser: &str, browser_version: &str, device: &str) {
+        let record = parse(user_agent);
+        assert_eq!(
+            (
+                record.browser.as_str(),
+                record.browser_version.as_str(),
+                record.device.as_str()
+            ),
+            (browser, browser_version, device),
+            "{}",
+            user_agent
+        );
+    }
+
+    #[test]
+    fn parse_curl() {
+        assert_parsed("curl/8.5.0", "curl", "8.5.0", "cli");
+        assert!(!parse("curl/8.5.0").is_browser());
+    }
+
+    #[test]
+    fn parse_browser() {
+        let user_agent =
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        assert_parsed(user_agent, "Firefox", "120.0", "desktop");
+        assert!(parse(user_agent).is_browser());
+    }
+
+    #[test]
+    fn parse_bot() {
+        let record =
+            parse("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)");
+        assert_eq!(record.device, "bot");
+        assert!(record.bot);
+        assert!(!record.is_browser());
+    }
+
+    #[test]
+    fn parse_api_client_versions_its_own_token() {
+        let user_agent = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+            (KHTML, like Gecko) insomnia/2023.5.8 Chrome/114.0.5735.289 Electron/25.8.4 \
+            Safari/537.36";
+        assert_parsed(user_agent, "Insomnia", "2023.5.8", "api-client");
+        assert!(!parse(user_agent).is_browser());
+        assert_parsed("PostmanRuntime/7.36.0", "Postman", "7.36.0", "api-client");
+        assert_parsed("vscode-restclient", "REST Client", "", "api-client");
+    }
+
+    #[test]
+    fn parse_unknown() {
+        assert_parsed("", "", "", "unknown");
+    }
+}
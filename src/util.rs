@@ -1,9 +1,15 @@
 use crate::format_middleware::Format;
 use crate::models::{ToCsv, ToPlainText};
+use crate::prefix_tree::PrefixTree;
+use crate::user_agent::request_user_agent;
 use actix_web::{HttpMessage, HttpRequest, HttpResponse};
 use serde::Serialize;
+use serde_json::Value;
 use std::net::{IpAddr, Ipv4Addr};
 
+const ENV_PREFIX: &str = "IPEEK";
+const MAX_CALLBACK_LENGTH: usize = 128;
+
 pub fn format_response<T, U>(format: &Format, data: &T, pretty: bool) -> HttpResponse
 where
     T: Serialize + ToPlainText + ToCsv<U> + yaserde::YaSerialize,
@@ -66,19 +72,182 @@ where
                 .body(bin_data),
             Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
         },
+        Format::Env => match serde_json::to_value(data) {
+            Ok(value) => {
+                let mut lines = Vec::new();
+                flatten_env(ENV_PREFIX, &value, &mut lines);
+                HttpResponse::Ok()
+                    .content_type("text/plain")
+                    .body(format!("{}\n", lines.join("\n")))
+            }
+            Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+        },
+        Format::Sse => match serde_json::to_string(data) {
+            Ok(json_str) => HttpResponse::Ok()
+                .content_type("text/event-stream")
+                .insert_header(("Cache-Control", "no-cache"))
+                .body(sse_event("result", &json_str)),
+            Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+        },
+        Format::Jsonp(callback) => {
+            if !is_valid_callback(callback) {
+                return HttpResponse::BadRequest().body("Invalid JSONP callback name\n");
+            }
+
+            match serde_json::to_string(data) {
+                Ok(json_str) => HttpResponse::Ok()
+                    .content_type("application/javascript")
+                    .insert_header(("X-Content-Type-Options", "nosniff"))
+                    .body(format!("/**/{}({});\n", callback, json_str)),
+                Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+            }
+        }
         Format::Plain | Format::Txt => HttpResponse::Ok()
             .content_type("text/plain")
             .body(format!("{}\n", data.to_plain_text())),
     }
 }
 
+// Encodes a single Server-Sent Event, `data` must not contain newlines (compact JSON)
+pub fn sse_event(event: &str, data: &str) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+// Flattens a serialized model into `PREFIX_KEY='value'` lines that are safe to `eval`
+fn flatten_env(key: &str, value: &Value, lines: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (name, nested) in map {
+                flatten_env(&format!("{}_{}", key, env_key(name)), nested, lines);
+            }
+        }
+        Value::Array(items) => {
+            lines.push(format!("{}_COUNT={}", key, items.len()));
+            for (index, nested) in items.iter().enumerate() {
+                flatten_env(&format!("{}_{}", key, index), nested, lines);
+            }
+        }
+        Value::Null => lines.push(format!("{}=''", key)),
+        Value::String(s) => lines.push(format!("{}={}", key, shell_quote(s))),
+        other => lines.push(format!("{}={}", key, shell_quote(&other.to_string()))),
+    }
+}
+
+fn env_key(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+// Only plain (optionally dotted) JavaScript identifiers are accepted as callbacks
+fn is_valid_callback(callback: &str) -> bool {
+    !callback.is_empty()
+        && callback.len() <= MAX_CALLBACK_LENGTH
+        && callback.split('.').all(|part| {
+            let mut chars = part.chars();
+            matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        })
+}
+
+// Formats a unix timestamp as a `YYYY-MM-DD` UTC date
+pub fn format_epoch_date(epoch: u64) -> String {
+    let days = (epoch / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Route pattern of the request, matched on the path without format extension
+pub fn matched_pattern(req: &HttpRequest) -> Option<String> {
+    req.resource_map().match_pattern(req.match_info().as_str())
+}
+
 pub fn get_ip(req: &HttpRequest) -> IpAddr {
+    get_ip_with_source(req).0
+}
+
+// Client address a caller can't choose, for connecting back and rate limiting. `get_ip` shows
+// whatever the headers claim
+pub fn get_trusted_ip(req: &HttpRequest, trusted_proxies: &PrefixTree<()>) -> IpAddr {
+    let forwarded_for: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    let real_ip = req
+        .headers()
+        .get("X-Real-IP")
+        .and_then(|value| value.to_str().ok());
+
+    trusted_ip(
+        req.peer_addr().map(|peer| peer.ip()),
+        &forwarded_for,
+        real_ip,
+        trusted_proxies,
+    )
+}
+
+// The peer unless it is a trusted proxy. Proxies append to `X-Forwarded-For`, so the header is
+// walked from the right and the first hop that isn't a trusted proxy is the client, everything
+// left of it was written by the client itself. `X-Real-IP` only counts from a trusted peer that
+// sent no `X-Forwarded-For`. Unix socket peers have no address and are local reverse proxies
+fn trusted_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: &[&str],
+    real_ip: Option<&str>,
+    trusted_proxies: &PrefixTree<()>,
+) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted_proxies.longest_match(ip).is_some();
+    let mut client = match peer {
+        Some(peer) if !is_trusted(peer) => return peer,
+        Some(peer) => peer,
+        None => IpAddr::V4(Ipv4Addr::LOCALHOST),
+    };
+
+    if forwarded_for.is_empty() {
+        return real_ip
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+            .unwrap_or(client);
+    }
+    for hop in forwarded_for.iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) if is_trusted(ip) => client = ip,
+            Ok(ip) => return ip,
+            // Garbage in the chain, the last trusted hop is all that is known
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+// Client IP together with where it was taken from (`x-forwarded-for`, `x-real-ip` or `peer`)
+pub fn get_ip_with_source(req: &HttpRequest) -> (IpAddr, &'static str) {
     if let Some(forwarded_for) = req.headers().get("X-Forwarded-For") {
         if let Ok(forwarded_for_str) = forwarded_for.to_str() {
             if let Some(ip) = forwarded_for_str.split(',').next() {
                 let ip_trimmed = ip.trim();
                 if let Ok(parsed_ip) = ip_trimmed.parse::<IpAddr>() {
-                    return parsed_ip;
+                    return (parsed_ip, "x-forwarded-for");
                 }
             }
         }
@@ -87,51 +256,44 @@ pub fn get_ip(req: &HttpRequest) -> IpAddr {
     if let Some(real_ip) = req.headers().get("X-Real-IP") {
         if let Ok(real_ip_str) = real_ip.to_str() {
             if let Ok(parsed_ip) = real_ip_str.trim().parse::<IpAddr>() {
-                return parsed_ip;
+                return (parsed_ip, "x-real-ip");
             }
         }
     }
 
-    req.connection_info()
+    let ip = req
+        .connection_info()
         .realip_remote_addr()
         .and_then(|ip| ip.parse::<IpAddr>().ok())
-        .unwrap_or(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+    (ip, "peer")
 }
 
-static KNOWN_BROWSERS: [&str; 8] = [
-    "chrome",
-    "firefox",
-    "safari",
-    "edge",
-    "opera",
-    "chromium",
-    "msie",
-    "internet explorer",
-];
+// Every hop the request passed: the `X-Forwarded-For` entries followed by the peer address
+pub fn get_proxy_chain(req: &HttpRequest) -> Vec<String> {
+    let mut chain: Vec<String> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim().to_string())
+        .filter(|hop| !hop.is_empty())
+        .collect();
 
-pub fn is_browser(req: &HttpRequest) -> bool {
-    if let Some(ua_value) = req.headers().get("User-Agent") {
-        if let Ok(ua_str) = ua_value.to_str() {
-            let ua_str_lower = ua_str.to_ascii_lowercase();
-            return KNOWN_BROWSERS.iter().any(|b| ua_str_lower.contains(b));
-        }
+    if let Some(peer) = req.peer_addr() {
+        chain.push(peer.ip().to_string());
     }
-    false
+
+    chain
 }
 
-static API_TOOLS: [&str; 8] = [
-    "postman",
-    "httpie",
-    "insomnia",
-    "swagger",
-    "apifox",
-    "soapui",
-    "paw/",
-    "rest-client",  // VS Code REST Client
-];
+pub fn is_browser(req: &HttpRequest) -> bool {
+    request_user_agent(req).is_browser()
+}
 
 pub fn client_supports_color(req: &HttpRequest) -> bool {
-    if is_browser(req) {
+    let user_agent = request_user_agent(req);
+    if user_agent.is_browser() || user_agent.is_api_client() {
         return false;
     }
 
@@ -139,15 +301,6 @@ pub fn client_supports_color(req: &HttpRequest) -> bool {
         return false;
     }
 
-    if let Some(ua_value) = req.headers().get("User-Agent") {
-        if let Ok(ua_str) = ua_value.to_str() {
-            let ua_str_lower = ua_str.to_ascii_lowercase();
-            if API_TOOLS.iter().any(|tool| ua_str_lower.contains(tool)) {
-                return false;
-            }
-        }
-    }
-
     if let Some(host) = req.headers().get("Host") {
         if let Ok(host_str) = host.to_str() {
             match host_str {
@@ -169,3 +322,204 @@ pub fn client_supports_color(req: &HttpRequest) -> bool {
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prefix_tree::parse_network;
+
+    fn proxies(networks: &[&str]) -> PrefixTree<()> {
+        let mut proxies = PrefixTree::default();
+        for network in networks {
+            proxies.insert(parse_network(network).unwrap(), ());
+        }
+        proxies
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn trusted_ip_ignores_headers_from_untrusted_peers() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let peer = Some(ip("198.51.100.7"));
+        assert_eq!(
+            trusted_ip(peer, &["203.0.113.1"], None, &trusted),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            trusted_ip(peer, &[], Some("203.0.113.1"), &trusted),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn trusted_ip_skips_a_spoofed_leftmost_entry() {
+        let trusted = proxies(&["10.0.0.1"]);
+        // The client sent `X-Forwarded-For: 203.0.113.1`, the proxy appended the real address
+        let forwarded_for = ["203.0.113.1", " 198.51.100.7"];
+        let client = trusted_ip(Some(ip("10.0.0.1")), &forwarded_for, None, &trusted);
+        assert_eq!(client, ip("198.51.100.7"));
+    }
+
+    #[test]
+    fn trusted_ip_walks_past_multiple_trusted_hops() {
+        let trusted = proxies(&["10.0.0.0/8", "192.0.2.10"]);
+        let forwarded_for = ["203.0.113.1", "198.51.100.7", "192.0.2.10", "10.1.1.1"];
+        let client = trusted_ip(Some(ip("10.0.0.1")), &forwarded_for, None, &trusted);
+        assert_eq!(client, ip("198.51.100.7"));
+
+        // Only trusted hops, the leftmost of them is the closest thing to a client
+        let forwarded_for = ["192.0.2.10", "10.1.1.1"];
+        let client = trusted_ip(Some(ip("10.0.0.1")), &forwarded_for, None, &trusted);
+        assert_eq!(client, ip("192.0.2.10"));
+    }
+
+    #[test]
+    fn trusted_ip_stops_at_garbage() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let forwarded_for = ["203.0.113.1", "unknown", "10.1.1.1"];
+        let client = trusted_ip(Some(ip("10.0.0.1")), &forwarded_for, None, &trusted);
+        assert_eq!(client, ip("10.1.1.1"));
+    }
+
+    #[test]
+    fn trusted_ip_uses_x_real_ip_from_trusted_peers_only_without_x_forwarded_for() {
+        let trusted = proxies(&["10.0.0.1"]);
+        let peer = Some(ip("10.0.0.1"));
+        assert_eq!(
+            trusted_ip(peer, &[], Some("198.51.100.7"), &trusted),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            trusted_ip(peer, &["198.51.100.7"], Some("203.0.113.1"), &trusted),
+            ip("198.51.100.7")
+        );
+        assert_eq!(trusted_ip(peer, &[], None, &trusted), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn trusted_ip_behind_unix_sockets() {
+        let trusted = proxies(&[]);
+        let forwarded_for = ["203.0.113.1", "198.51.100.7"];
+        assert_eq!(
+            trusted_ip(None, &forwarded_for, None, &trusted),
+            ip("198.51.100.7")
+        );
+        assert_eq!(trusted_ip(None, &[], None, &trusted), ip("127.0.0.1"));
+    }
+
+    fn env_lines(value: Value) -> Vec<String> {
+        let mut lines = Vec::new();
+        flatten_env("IPEEK", &value, &mut lines);
+        lines
+    }
+
+    #[test]
+    fn shell_quote_keeps_values_literal() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("''"), r"''\'''\'''");
+        assert_eq!(shell_quote("$(id) `id` $HOME"), "'$(id) `id` $HOME'");
+        assert_eq!(shell_quote("a\nb"), "'a\nb'");
+    }
+
+    #[test]
+    fn flatten_env_quotes_every_value() {
+        let lines = env_lines(serde_json::json!({
+            "ip": "192.0.2.1",
+            "hostname": "it's $(reboot)",
+            "note": "first\nsecond",
+            "asn": 64500,
+            "bot": false,
+            "organization": null,
+        }));
+        assert_eq!(
+            lines,
+            [
+                "IPEEK_IP='192.0.2.1'",
+                r"IPEEK_HOSTNAME='it'\''s $(reboot)'",
+                "IPEEK_NOTE='first\nsecond'",
+                "IPEEK_ASN='64500'",
+                "IPEEK_BOT='false'",
+                "IPEEK_ORGANIZATION=''",
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_env_output_evaluates_to_the_original_values() {
+        let value = "it's \"$(echo injected)\" `echo injected` $HOME\nline";
+        let mut script = env_lines(serde_json::json!({ "hostname": value })).join("\n");
+        script.push_str("\nprintf %s \"$IPEEK_HOSTNAME\"");
+
+        let output = std::process::Command::new("sh")
+            .args(["-c", &script])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), value);
+    }
+
+    #[test]
+    fn flatten_env_counts_nested_arrays() {
+        let lines = env_lines(serde_json::json!({
+            "blocklist": {
+                "listed-on": [["zen", "sbl"], []],
+            },
+        }));
+        assert_eq!(
+            lines,
+            [
+                "IPEEK_BLOCKLIST_LISTED_ON_COUNT=2",
+                "IPEEK_BLOCKLIST_LISTED_ON_0_COUNT=2",
+                "IPEEK_BLOCKLIST_LISTED_ON_0_0='zen'",
+                "IPEEK_BLOCKLIST_LISTED_ON_0_1='sbl'",
+                "IPEEK_BLOCKLIST_LISTED_ON_1_COUNT=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn is_valid_callback_accepts_dotted_identifiers() {
+        for callback in ["cb", "_cb", "$", "jQuery123_456", "window.app.$handle"] {
+            assert!(is_valid_callback(callback), "{}", callback);
+        }
+        assert!(is_valid_callback(&"a".repeat(MAX_CALLBACK_LENGTH)));
+    }
+
+    #[test]
+    fn is_valid_callback_rejects_everything_else() {
+        let too_long = "a".repeat(MAX_CALLBACK_LENGTH + 1);
+        for callback in [
+            "", "a(b)", "a..b", ".a", "a.", "1cb", "a.1b", "a b", "a;b", &too_long,
+        ] {
+            assert!(!is_valid_callback(callback), "{}", callback);
+        }
+    }
+
+    #[test]
+    fn format_epoch_date_at_epoch_boundaries() {
+        assert_eq!(format_epoch_date(0), "1970-01-01");
+        assert_eq!(format_epoch_date(86_399), "1970-01-01");
+        assert_eq!(format_epoch_date(86_400), "1970-01-02");
+        assert_eq!(format_epoch_date(946_684_799), "1999-12-31");
+        assert_eq!(format_epoch_date(946_684_800), "2000-01-01");
+        assert_eq!(format_epoch_date(2_147_483_647), "2038-01-19");
+        assert_eq!(format_epoch_date(4_294_967_295), "2106-02-07");
+    }
+
+    #[test]
+    fn format_epoch_date_in_leap_years() {
+        // 2000 is a leap year since it is divisible by 400, 2100 is not
+        assert_eq!(format_epoch_date(951_782_400), "2000-02-29");
+        assert_eq!(format_epoch_date(951_868_800), "2000-03-01");
+        assert_eq!(format_epoch_date(1_709_164_800), "2024-02-29");
+        assert_eq!(format_epoch_date(1_735_603_200), "2024-12-31");
+        assert_eq!(format_epoch_date(1_740_700_800), "2025-02-28");
+        assert_eq!(format_epoch_date(1_740_787_200), "2025-03-01");
+        assert_eq!(format_epoch_date(4_107_456_000), "2100-02-28");
+        assert_eq!(format_epoch_date(4_107_542_400), "2100-03-01");
+    }
+}